@@ -0,0 +1,354 @@
+//! Derives for the typed AST traits of `rowan-test`, [`ast::AstNode`] and
+//! [`ast::AstToken`]. Enable the `derive` feature of `rowan-test` to use them
+//! through its `ast` module.
+//!
+//! [`ast::AstNode`]: https://docs.rs/rowan-test/*/rowan_test/ast/trait.AstNode.html
+//! [`ast::AstToken`]: https://docs.rs/rowan-test/*/rowan_test/ast/trait.AstToken.html
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{
+    parse_macro_input, punctuated::Punctuated, Attribute, Data, DataEnum, DataStruct, DeriveInput,
+    Error, Expr, Fields, Member, Token, Type,
+};
+
+/// Implements `AstNode`, along with `Display` forwarding to the syntax node.
+///
+/// On a struct wrapping a single `SyntaxNode`, the `#[kind(...)]` attribute
+/// lists the kinds of the nodes it can be cast from:
+///
+/// ```
+/// # use rowan_test::{ast::AstNode, Language, SyntaxKind, SyntaxNode};
+/// # #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+/// # enum Yara {}
+/// # impl Language for Yara {
+/// #     type Kind = SyntaxKind;
+/// #     fn kind_from_raw(raw: SyntaxKind) -> SyntaxKind { raw }
+/// #     fn kind_to_raw(kind: SyntaxKind) -> SyntaxKind { kind }
+/// # }
+/// # const RULE: SyntaxKind = SyntaxKind(0);
+/// # const PRIVATE_RULE: SyntaxKind = SyntaxKind(1);
+/// #[derive(AstNode)]
+/// #[kind(RULE, PRIVATE_RULE)]
+/// struct Rule(SyntaxNode<Yara>);
+/// ```
+///
+/// On an enum, each variant must wrap a single AST type. A node is cast to
+/// the first variant which accepts it. `From` conversions from the variant
+/// types to the enum and `TryFrom` conversions back are generated as well:
+///
+/// ```
+/// # use rowan_test::{ast::AstNode, Language, SyntaxKind, SyntaxNode};
+/// # #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+/// # enum Yara {}
+/// # impl Language for Yara {
+/// #     type Kind = SyntaxKind;
+/// #     fn kind_from_raw(raw: SyntaxKind) -> SyntaxKind { raw }
+/// #     fn kind_to_raw(kind: SyntaxKind) -> SyntaxKind { kind }
+/// # }
+/// # #[derive(AstNode)]
+/// # #[kind(SyntaxKind(0))]
+/// # struct BinaryExpr(SyntaxNode<Yara>);
+/// # #[derive(AstNode)]
+/// # #[kind(SyntaxKind(1))]
+/// # struct Literal(SyntaxNode<Yara>);
+/// #[derive(AstNode)]
+/// enum Expr {
+///     Binary(BinaryExpr),
+///     Literal(Literal),
+/// }
+/// ```
+#[proc_macro_derive(AstNode, attributes(kind))]
+pub fn derive_ast_node(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(&input, Trait::AstNode)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+/// Implements `AstToken`, along with `Display` forwarding to the syntax
+/// token. Takes the same forms as [`macro@AstNode`], with structs wrapping a
+/// `SyntaxToken` instead.
+#[proc_macro_derive(AstToken, attributes(kind))]
+pub fn derive_ast_token(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(&input, Trait::AstToken)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+#[derive(Clone, Copy)]
+enum Trait {
+    AstNode,
+    AstToken,
+}
+
+impl Trait {
+    fn path(self) -> TokenStream2 {
+        match self {
+            Trait::AstNode => quote!(::rowan_test::ast::AstNode),
+            Trait::AstToken => quote!(::rowan_test::ast::AstToken),
+        }
+    }
+
+    fn syntax(self) -> TokenStream2 {
+        match self {
+            Trait::AstNode => quote!(::rowan_test::SyntaxNode),
+            Trait::AstToken => quote!(::rowan_test::SyntaxToken),
+        }
+    }
+}
+
+fn expand(input: &DeriveInput, tr: Trait) -> syn::Result<TokenStream2> {
+    let imp = match &input.data {
+        Data::Struct(data) => expand_struct(input, data, tr)?,
+        Data::Enum(data) => expand_enum(input, data, tr)?,
+        Data::Union(_) => {
+            return Err(Error::new_spanned(
+                &input.ident,
+                "AST types must be structs or enums",
+            ))
+        }
+    };
+    let name = &input.ident;
+    let trait_path = tr.path();
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        #imp
+
+        impl #impl_generics ::core::fmt::Display for #name #ty_generics #where_clause {
+            fn fmt(&self, f: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
+                ::core::fmt::Display::fmt(#trait_path::syntax(self), f)
+            }
+        }
+    })
+}
+
+fn expand_struct(input: &DeriveInput, data: &DataStruct, tr: Trait) -> syn::Result<TokenStream2> {
+    let kinds = kinds(&input.attrs)?;
+    if kinds.is_empty() {
+        return Err(Error::new_spanned(
+            &input.ident,
+            "expected a `#[kind(...)]` attribute listing the kinds to cast from",
+        ));
+    }
+    let (member, ty) = match &data.fields {
+        Fields::Unnamed(fields) if fields.unnamed.len() == 1 => {
+            (Member::from(0), &fields.unnamed[0].ty)
+        }
+        Fields::Named(fields) if fields.named.len() == 1 => {
+            let field = &fields.named[0];
+            (Member::from(field.ident.clone().unwrap()), &field.ty)
+        }
+        _ => {
+            return Err(Error::new_spanned(
+                &input.ident,
+                "expected a struct with a single field holding the syntax",
+            ))
+        }
+    };
+
+    let name = &input.ident;
+    let trait_path = tr.path();
+    let syntax = tr.syntax();
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics #trait_path for #name #ty_generics #where_clause {
+            type Language = <#ty as ::rowan_test::ast::__derive::HasLanguage>::Language;
+
+            fn can_cast(kind: <Self::Language as ::rowan_test::Language>::Kind) -> bool {
+                #(kind == #kinds)||*
+            }
+
+            fn cast(syntax: #syntax<Self::Language>) -> ::core::option::Option<Self> {
+                if <Self as #trait_path>::can_cast(syntax.kind()) {
+                    ::core::option::Option::Some(Self { #member: syntax })
+                } else {
+                    ::core::option::Option::None
+                }
+            }
+
+            fn syntax(&self) -> &#syntax<Self::Language> {
+                &self.#member
+            }
+        }
+    })
+}
+
+fn expand_enum(input: &DeriveInput, data: &DataEnum, tr: Trait) -> syn::Result<TokenStream2> {
+    if let Some(attr) = input.attrs.iter().find(|it| it.path().is_ident("kind")) {
+        return Err(Error::new_spanned(
+            attr,
+            "the kinds of an enum are those of its variants",
+        ));
+    }
+    let mut variants = Vec::new();
+    let mut tys: Vec<&Type> = Vec::new();
+    for variant in &data.variants {
+        match &variant.fields {
+            Fields::Unnamed(fields) if fields.unnamed.len() == 1 => {
+                let ty = &fields.unnamed[0].ty;
+                // The `From` and `TryFrom` impls of two variants holding the
+                // same type would conflict.
+                if tys
+                    .iter()
+                    .any(|it| quote!(#it).to_string() == quote!(#ty).to_string())
+                {
+                    return Err(Error::new_spanned(
+                        ty,
+                        "another variant already holds this type",
+                    ));
+                }
+                variants.push(&variant.ident);
+                tys.push(ty);
+            }
+            _ => {
+                return Err(Error::new_spanned(
+                    variant,
+                    "expected a variant with a single field holding an AST type",
+                ))
+            }
+        }
+    }
+    let first_ty = match tys.first() {
+        Some(it) => it,
+        None => {
+            return Err(Error::new_spanned(
+                &input.ident,
+                "expected at least one variant",
+            ))
+        }
+    };
+
+    let name = &input.ident;
+    let trait_path = tr.path();
+    let syntax = tr.syntax();
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics #trait_path for #name #ty_generics #where_clause {
+            type Language = <#first_ty as #trait_path>::Language;
+
+            fn can_cast(kind: <Self::Language as ::rowan_test::Language>::Kind) -> bool {
+                #(<#tys as #trait_path>::can_cast(kind))||*
+            }
+
+            fn cast(syntax: #syntax<Self::Language>) -> ::core::option::Option<Self> {
+                let kind = syntax.kind();
+                #(
+                    if <#tys as #trait_path>::can_cast(kind) {
+                        return <#tys as #trait_path>::cast(syntax).map(#name::#variants);
+                    }
+                )*
+                ::core::option::Option::None
+            }
+
+            fn syntax(&self) -> &#syntax<Self::Language> {
+                match self {
+                    #(#name::#variants(it) => <#tys as #trait_path>::syntax(it),)*
+                }
+            }
+        }
+
+        #(
+            impl #impl_generics ::core::convert::From<#tys> for #name #ty_generics #where_clause {
+                fn from(it: #tys) -> Self {
+                    #name::#variants(it)
+                }
+            }
+
+            impl #impl_generics ::core::convert::TryFrom<#name #ty_generics> for #tys #where_clause {
+                type Error = #name #ty_generics;
+
+                #[allow(unreachable_patterns)]
+                fn try_from(it: #name #ty_generics) -> ::core::result::Result<Self, Self::Error> {
+                    match it {
+                        #name::#variants(it) => ::core::result::Result::Ok(it),
+                        other => ::core::result::Result::Err(other),
+                    }
+                }
+            }
+        )*
+    })
+}
+
+/// The kinds listed by all `#[kind(...)]` attributes.
+fn kinds(attrs: &[Attribute]) -> syn::Result<Vec<Expr>> {
+    let mut res = Vec::new();
+    for attr in attrs.iter().filter(|it| it.path().is_ident("kind")) {
+        let kinds = attr.parse_args_with(Punctuated::<Expr, Token![,]>::parse_terminated)?;
+        res.extend(kinds);
+    }
+    Ok(res)
+}
+
+/// The forms the derives reject:
+///
+/// ```compile_fail
+/// # use rowan_test::{ast::AstNode, Language, SyntaxKind, SyntaxNode};
+/// # #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+/// # enum Yara {}
+/// # impl Language for Yara {
+/// #     type Kind = SyntaxKind;
+/// #     fn kind_from_raw(raw: SyntaxKind) -> SyntaxKind { raw }
+/// #     fn kind_to_raw(kind: SyntaxKind) -> SyntaxKind { kind }
+/// # }
+/// #[derive(AstNode)]
+/// struct Rule(SyntaxNode<Yara>);
+/// ```
+///
+/// ```compile_fail
+/// # use rowan_test::{ast::AstNode, Language, SyntaxKind, SyntaxNode};
+/// # #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+/// # enum Yara {}
+/// # impl Language for Yara {
+/// #     type Kind = SyntaxKind;
+/// #     fn kind_from_raw(raw: SyntaxKind) -> SyntaxKind { raw }
+/// #     fn kind_to_raw(kind: SyntaxKind) -> SyntaxKind { kind }
+/// # }
+/// # #[derive(AstNode)]
+/// # #[kind(SyntaxKind(0))]
+/// # struct Rule(SyntaxNode<Yara>);
+/// #[derive(AstNode)]
+/// #[kind(SyntaxKind(0))]
+/// enum Item {
+///     Rule(Rule),
+/// }
+/// ```
+///
+/// ```compile_fail
+/// # use rowan_test::{ast::AstNode, Language, SyntaxKind, SyntaxNode};
+/// # #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+/// # enum Yara {}
+/// # impl Language for Yara {
+/// #     type Kind = SyntaxKind;
+/// #     fn kind_from_raw(raw: SyntaxKind) -> SyntaxKind { raw }
+/// #     fn kind_to_raw(kind: SyntaxKind) -> SyntaxKind { kind }
+/// # }
+/// #[derive(AstNode)]
+/// #[kind(SyntaxKind(0))]
+/// union Rule {
+///     node: std::mem::ManuallyDrop<SyntaxNode<Yara>>,
+/// }
+/// ```
+///
+/// ```compile_fail
+/// # use rowan_test::{ast::AstNode, Language, SyntaxKind, SyntaxNode};
+/// # #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+/// # enum Yara {}
+/// # impl Language for Yara {
+/// #     type Kind = SyntaxKind;
+/// #     fn kind_from_raw(raw: SyntaxKind) -> SyntaxKind { raw }
+/// #     fn kind_to_raw(kind: SyntaxKind) -> SyntaxKind { kind }
+/// # }
+/// # #[derive(AstNode)]
+/// # #[kind(SyntaxKind(0))]
+/// # struct Rule(SyntaxNode<Yara>);
+/// #[derive(AstNode)]
+/// enum Item {
+///     Public(Rule),
+///     Private(Rule),
+/// }
+/// ```
+#[cfg(doctest)]
+struct CompileFail;
@@ -0,0 +1,113 @@
+use rowan_test::{
+    ast::{support, AstNode, AstToken},
+    GreenNodeBuilder, Language, SyntaxKind,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+enum Yara {}
+
+impl Language for Yara {
+    type Kind = SyntaxKind;
+
+    fn kind_from_raw(raw: SyntaxKind) -> SyntaxKind {
+        raw
+    }
+    fn kind_to_raw(kind: SyntaxKind) -> SyntaxKind {
+        kind
+    }
+}
+
+type SyntaxNode = rowan_test::SyntaxNode<Yara>;
+type SyntaxToken = rowan_test::SyntaxToken<Yara>;
+
+const CONDITION: SyntaxKind = SyntaxKind(0);
+const AND_EXPR: SyntaxKind = SyntaxKind(1);
+const OR_EXPR: SyntaxKind = SyntaxKind(2);
+const LITERAL: SyntaxKind = SyntaxKind(3);
+const TRUE_KW: SyntaxKind = SyntaxKind(10);
+const AND_KW: SyntaxKind = SyntaxKind(11);
+const INT: SyntaxKind = SyntaxKind(12);
+const WS: SyntaxKind = SyntaxKind(13);
+
+#[derive(Debug, AstNode)]
+#[kind(AND_EXPR, OR_EXPR)]
+struct BinaryExpr(SyntaxNode);
+
+#[derive(Debug, AstNode)]
+#[kind(LITERAL)]
+struct Literal {
+    syntax: SyntaxNode,
+}
+
+#[derive(Debug, AstNode)]
+enum Expr {
+    Binary(BinaryExpr),
+    Literal(Literal),
+}
+
+#[derive(Debug, AstToken)]
+#[kind(INT)]
+struct IntNumber(SyntaxToken);
+
+#[derive(Debug, AstToken)]
+#[kind(TRUE_KW)]
+struct TrueKw(SyntaxToken);
+
+#[derive(Debug, AstToken)]
+enum LiteralToken {
+    Int(IntNumber),
+    True(TrueKw),
+}
+
+// CONDITION(AND_EXPR(LITERAL("true") " " "and" " " LITERAL("42")))
+fn parse() -> SyntaxNode {
+    let mut builder = GreenNodeBuilder::new();
+    builder.start_node(CONDITION);
+    builder.start_node(AND_EXPR);
+    builder.start_node(LITERAL);
+    builder.token(TRUE_KW, "true");
+    builder.finish_node();
+    builder.token(WS, " ");
+    builder.token(AND_KW, "and");
+    builder.token(WS, " ");
+    builder.start_node(LITERAL);
+    builder.token(INT, "42");
+    builder.finish_node();
+    builder.finish_node();
+    builder.finish_node();
+    SyntaxNode::new_root(builder.finish())
+}
+
+#[test]
+fn derived_nodes() {
+    let condition = parse();
+    assert!(BinaryExpr::cast(condition.clone()).is_none());
+    assert!(Expr::can_cast(OR_EXPR));
+    assert!(!Expr::can_cast(CONDITION));
+
+    let expr: Expr = support::child(&condition).unwrap();
+    assert_eq!(expr.to_string(), "true and 42");
+    let binary = BinaryExpr::try_from(expr).unwrap();
+    let operands: Vec<Expr> = support::children(binary.syntax()).collect();
+    assert!(matches!(operands[..], [Expr::Literal(_), Expr::Literal(_)]));
+
+    let literal = Literal::try_from(Expr::from(binary)).unwrap_err();
+    assert_eq!(literal.syntax().kind(), AND_EXPR);
+}
+
+#[test]
+fn derived_tokens() {
+    let condition = parse();
+    let literals = condition
+        .descendants()
+        .filter_map(Literal::cast)
+        .map(|it| support::token_typed::<LiteralToken>(it.syntax()).unwrap())
+        .collect::<Vec<_>>();
+    assert!(matches!(
+        literals[..],
+        [LiteralToken::True(_), LiteralToken::Int(_)]
+    ));
+    assert_eq!(literals[1].to_string(), "42");
+    let int = IntNumber::try_from(literals.into_iter().nth(1).unwrap()).unwrap();
+    assert_eq!(int.text(), "42");
+}
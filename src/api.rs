@@ -1,8 +1,9 @@
 use std::{borrow::Cow, fmt, iter, marker::PhantomData, ops::Range};
 
 use crate::{
-    green::GreenTokenData, red, Direction, GreenNode, GreenNodeData, GreenToken, NodeOrToken,
-    SyntaxKind, SyntaxText, TextRange, TextSize, TokenAtOffset, WalkEvent,
+    dump::kind_to_string, green::GreenTokenData, red, Direction, GreenNode, GreenNodeData,
+    GreenToken, Indel, NodeOrToken, RelexError, SyntaxKind, SyntaxText, TextRange, TextSize,
+    TokenAtOffset, TriviaAttachment, WalkEvent,
 };
 
 pub trait Language: Sized + Copy + fmt::Debug + Eq + Ord + std::hash::Hash {
@@ -10,6 +11,26 @@ pub trait Language: Sized + Copy + fmt::Debug + Eq + Ord + std::hash::Hash {
 
     fn kind_from_raw(raw: SyntaxKind) -> Self::Kind;
     fn kind_to_raw(kind: Self::Kind) -> SyntaxKind;
+
+    /// Human-readable name of the kind, used by tree dumps and `Debug`.
+    /// Without one, the `Debug` representation of the kind is used.
+    fn kind_name(kind: Self::Kind) -> Option<&'static str> {
+        let _ = kind;
+        None
+    }
+
+    /// Whether tokens of this kind, such as whitespace and comments, carry no
+    /// meaning for the parser.
+    fn is_trivia(kind: Self::Kind) -> bool {
+        let _ = kind;
+        false
+    }
+
+    /// How trivia is split between the tokens around it, see
+    /// [`SyntaxToken::leading_trivia`].
+    fn trivia_attachment() -> TriviaAttachment {
+        TriviaAttachment::default()
+    }
 }
 
 #[derive(Clone, PartialEq, Eq, Hash)]
@@ -48,7 +69,12 @@ impl<L: Language> fmt::Debug for SyntaxNode<L> {
             assert_eq!(level, 0);
             Ok(())
         } else {
-            write!(f, "{:?}@{:?}", self.kind(), self.text_range())
+            write!(
+                f,
+                "{}@{:?}",
+                kind_to_string::<L>(self.raw.kind()),
+                self.text_range()
+            )
         }
     }
 }
@@ -61,7 +87,12 @@ impl<L: Language> fmt::Display for SyntaxNode<L> {
 
 impl<L: Language> fmt::Debug for SyntaxToken<L> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{:?}@{:?}", self.kind(), self.text_range())?;
+        write!(
+            f,
+            "{}@{:?}",
+            kind_to_string::<L>(self.raw.kind()),
+            self.text_range()
+        )?;
         if self.text().len() < 25 {
             return write!(f, " {:?}", self.text());
         }
@@ -121,6 +152,11 @@ impl<L: Language> SyntaxNode<L> {
         self.raw.text()
     }
 
+    /// Dumps the subtree, naming kinds via [`Language::kind_name`].
+    pub fn dump(&self) -> String {
+        self.raw.dump(kind_to_string::<L>)
+    }
+
     pub fn green(&self) -> Cow<'_, GreenNodeData> {
         self.raw.green()
     }
@@ -260,6 +296,12 @@ impl<L: Language> SyntaxNode<L> {
         SyntaxNode::from(self.raw.clone_for_update())
     }
 
+    /// Returns `true` if this node belongs to a tree created by
+    /// `clone_for_update`.
+    pub fn is_mutable(&self) -> bool {
+        self.raw.is_mutable()
+    }
+
     pub fn detach(&self) {
         self.raw.detach()
     }
@@ -281,6 +323,18 @@ impl<L: Language> SyntaxToken<L> {
         self.raw.replace_with(new_token)
     }
 
+    /// Applies an edit contained in this token by relexing just the token,
+    /// and returns the new root. `relex` returns the kind of the edited text
+    /// if it is exactly one token. Fails if the kind changes or the token
+    /// would merge with one of its neighbours, in which case the caller should
+    /// reparse instead.
+    pub fn relex<F>(&self, edit: &Indel, mut relex: F) -> Result<GreenNode, RelexError>
+    where
+        F: FnMut(&str) -> Option<L::Kind>,
+    {
+        self.raw.relex(edit, |text| relex(text).map(L::kind_to_raw))
+    }
+
     pub fn kind(&self) -> L::Kind {
         L::kind_from_raw(self.raw.kind())
     }
@@ -341,6 +395,12 @@ impl<L: Language> SyntaxToken<L> {
         self.raw.prev_token().map(SyntaxToken::from)
     }
 
+    /// Returns `true` if this token belongs to a tree created by
+    /// `clone_for_update`.
+    pub fn is_mutable(&self) -> bool {
+        self.raw.is_mutable()
+    }
+
     pub fn detach(&self) {
         self.raw.detach()
     }
@@ -395,6 +455,14 @@ impl<L: Language> SyntaxElement<L> {
             NodeOrToken::Token(it) => it.prev_sibling_or_token(),
         }
     }
+
+    pub fn is_mutable(&self) -> bool {
+        match self {
+            NodeOrToken::Node(it) => it.is_mutable(),
+            NodeOrToken::Token(it) => it.is_mutable(),
+        }
+    }
+
     pub fn detach(&self) {
         match self {
             NodeOrToken::Node(it) => it.detach(),
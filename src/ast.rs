@@ -8,10 +8,12 @@
 //! Instead, we provide a [`SyntaxNodePtr`] type, which stores information about
 //! the _location_ of a particular syntax node in a tree. It's a small type
 //! which can be cheaply stored, and which can be resolved to a real
-//! [`SyntaxNode`] when necessary.
+//! [`SyntaxNode`] when necessary. [`SyntaxTokenPtr`] and [`SyntaxElementPtr`]
+//! do the same for tokens and for either of the two.
 //!
-//! We also provide an [`AstNode`] trait for typed AST wrapper APIs over rowan
-//! nodes.
+//! We also provide [`AstNode`] and [`AstToken`] traits for typed AST wrapper
+//! APIs over rowan nodes and tokens. With the `derive` feature, they can be
+//! implemented with `#[derive(AstNode)]` and `#[derive(AstToken)]`.
 
 use std::{
     fmt,
@@ -20,7 +22,13 @@ use std::{
     marker::PhantomData,
 };
 
-use crate::{Language, SyntaxNode, SyntaxNodeChildren, TextRange};
+use crate::{
+    Language, NodeOrToken, SyntaxElement, SyntaxElementChildren, SyntaxNode, SyntaxNodeChildren,
+    SyntaxToken, TextRange,
+};
+
+#[cfg(feature = "derive")]
+pub use rowan_test_derive::{AstNode, AstToken};
 
 /// The main trait to go from untyped [`SyntaxNode`] to a typed AST. The
 /// conversion itself has zero runtime cost: AST and syntax nodes have exactly
@@ -54,6 +62,26 @@ pub trait AstNode {
     }
 }
 
+/// Like [`AstNode`], but wraps tokens, such as literals whose text needs to be
+/// interpreted.
+pub trait AstToken {
+    type Language: Language;
+
+    fn can_cast(kind: <Self::Language as Language>::Kind) -> bool
+    where
+        Self: Sized;
+
+    fn cast(token: SyntaxToken<Self::Language>) -> Option<Self>
+    where
+        Self: Sized;
+
+    fn syntax(&self) -> &SyntaxToken<Self::Language>;
+
+    fn text(&self) -> &str {
+        self.syntax().text()
+    }
+}
+
 /// A "pointer" to a [`SyntaxNode`], via location in the source code.
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub struct SyntaxNodePtr<L: Language> {
@@ -90,13 +118,9 @@ impl<L: Language> SyntaxNodePtr<L> {
     /// tree width. As most trees are shallow, thinking about this as
     /// `O(log(N))` in the size of the tree is not too wrong!
     pub fn try_to_node(&self, root: &SyntaxNode<L>) -> Option<SyntaxNode<L>> {
-        if root.parent().is_some() {
-            return None;
-        }
-        successors(Some(root.clone()), |node| {
-            node.child_or_token_at_range(self.range)?.into_node()
-        })
-        .find(|it| it.text_range() == self.range && it.kind() == self.kind)
+        descend(root, self.range)
+            .map_while(|it| it.into_node())
+            .find(|it| it.text_range() == self.range && it.kind() == self.kind)
     }
 
     /// Casts this to an [`AstPtr`] to the given node type if possible.
@@ -118,6 +142,141 @@ impl<L: Language> SyntaxNodePtr<L> {
     }
 }
 
+/// A "pointer" to a [`SyntaxToken`], via location in the source code.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct SyntaxTokenPtr<L: Language> {
+    kind: L::Kind,
+    range: TextRange,
+}
+
+impl<L: Language> SyntaxTokenPtr<L> {
+    /// Returns a [`SyntaxTokenPtr`] for the token.
+    pub fn new(token: &SyntaxToken<L>) -> Self {
+        Self {
+            kind: token.kind(),
+            range: token.text_range(),
+        }
+    }
+
+    /// Like [`Self::try_to_token`] but panics instead of returning `None` on
+    /// failure.
+    pub fn to_token(&self, root: &SyntaxNode<L>) -> SyntaxToken<L> {
+        self.try_to_token(root)
+            .unwrap_or_else(|| panic!("can't resolve {self:?} with {root:?}"))
+    }
+
+    /// "Dereferences" the pointer to get the [`SyntaxToken`] it points to,
+    /// with the same caveats and complexity as
+    /// [`SyntaxNodePtr::try_to_node`].
+    pub fn try_to_token(&self, root: &SyntaxNode<L>) -> Option<SyntaxToken<L>> {
+        descend(root, self.range)
+            .filter_map(|it| it.into_token())
+            .find(|it| it.text_range() == self.range && it.kind() == self.kind)
+    }
+
+    /// Returns the kind of the syntax token this points to.
+    pub fn kind(&self) -> L::Kind {
+        self.kind
+    }
+
+    /// Returns the range of the syntax token this points to.
+    pub fn text_range(&self) -> TextRange {
+        self.range
+    }
+}
+
+/// A "pointer" to a [`SyntaxElement`], either a [`SyntaxNodePtr`] or a
+/// [`SyntaxTokenPtr`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct SyntaxElementPtr<L: Language> {
+    raw: NodeOrToken<SyntaxNodePtr<L>, SyntaxTokenPtr<L>>,
+}
+
+impl<L: Language> SyntaxElementPtr<L> {
+    /// Returns a [`SyntaxElementPtr`] for the element.
+    pub fn new(element: &SyntaxElement<L>) -> Self {
+        let raw = match element {
+            NodeOrToken::Node(it) => NodeOrToken::Node(SyntaxNodePtr::new(it)),
+            NodeOrToken::Token(it) => NodeOrToken::Token(SyntaxTokenPtr::new(it)),
+        };
+        Self { raw }
+    }
+
+    /// Like [`Self::try_to_element`] but panics instead of returning `None`
+    /// on failure.
+    pub fn to_element(&self, root: &SyntaxNode<L>) -> SyntaxElement<L> {
+        self.try_to_element(root)
+            .unwrap_or_else(|| panic!("can't resolve {self:?} with {root:?}"))
+    }
+
+    /// "Dereferences" the pointer to get the [`SyntaxElement`] it points to,
+    /// see [`SyntaxNodePtr::try_to_node`].
+    pub fn try_to_element(&self, root: &SyntaxNode<L>) -> Option<SyntaxElement<L>> {
+        match &self.raw {
+            NodeOrToken::Node(it) => it.try_to_node(root).map(NodeOrToken::Node),
+            NodeOrToken::Token(it) => it.try_to_token(root).map(NodeOrToken::Token),
+        }
+    }
+
+    /// Returns the node pointer, if this points to a node.
+    pub fn as_node_ptr(&self) -> Option<SyntaxNodePtr<L>> {
+        self.raw.as_node().copied()
+    }
+
+    /// Returns the token pointer, if this points to a token.
+    pub fn as_token_ptr(&self) -> Option<SyntaxTokenPtr<L>> {
+        self.raw.as_token().copied()
+    }
+
+    /// Returns the kind of the syntax element this points to.
+    pub fn kind(&self) -> L::Kind {
+        match &self.raw {
+            NodeOrToken::Node(it) => it.kind(),
+            NodeOrToken::Token(it) => it.kind(),
+        }
+    }
+
+    /// Returns the range of the syntax element this points to.
+    pub fn text_range(&self) -> TextRange {
+        match &self.raw {
+            NodeOrToken::Node(it) => it.text_range(),
+            NodeOrToken::Token(it) => it.text_range(),
+        }
+    }
+}
+
+impl<L: Language> From<SyntaxNodePtr<L>> for SyntaxElementPtr<L> {
+    fn from(ptr: SyntaxNodePtr<L>) -> SyntaxElementPtr<L> {
+        SyntaxElementPtr {
+            raw: NodeOrToken::Node(ptr),
+        }
+    }
+}
+
+impl<L: Language> From<SyntaxTokenPtr<L>> for SyntaxElementPtr<L> {
+    fn from(ptr: SyntaxTokenPtr<L>) -> SyntaxElementPtr<L> {
+        SyntaxElementPtr {
+            raw: NodeOrToken::Token(ptr),
+        }
+    }
+}
+
+/// The elements from `root` down to the innermost one covering `range`,
+/// picking the child at each level by binary search. Empty if `root` is not
+/// actually a root.
+fn descend<L: Language>(
+    root: &SyntaxNode<L>,
+    range: TextRange,
+) -> impl Iterator<Item = SyntaxElement<L>> {
+    let root = match root.parent() {
+        Some(_) => None,
+        None => Some(NodeOrToken::Node(root.clone())),
+    };
+    successors(root, move |it: &SyntaxElement<L>| {
+        it.as_node()?.child_or_token_at_range(range)
+    })
+}
+
 /// Like [`SyntaxNodePtr`], but remembers the type of node.
 pub struct AstPtr<N: AstNode> {
     raw: SyntaxNodePtr<N::Language>,
@@ -213,8 +372,50 @@ impl<N: AstNode> Iterator for AstChildren<N> {
     }
 }
 
+/// Like [`AstChildren`], but for the child tokens of a node.
+#[derive(Debug, Clone)]
+pub struct AstTokenChildren<T: AstToken> {
+    inner: SyntaxElementChildren<T::Language>,
+    ph: PhantomData<T>,
+}
+
+impl<T: AstToken> AstTokenChildren<T> {
+    fn new(parent: &SyntaxNode<T::Language>) -> Self {
+        AstTokenChildren {
+            inner: parent.children_with_tokens(),
+            ph: PhantomData,
+        }
+    }
+}
+
+impl<T: AstToken> Iterator for AstTokenChildren<T> {
+    type Item = T;
+    fn next(&mut self) -> Option<T> {
+        self.inner.find_map(|it| T::cast(it.into_token()?))
+    }
+}
+
+#[doc(hidden)]
+pub mod __derive {
+    use crate::{Language, SyntaxNode, SyntaxToken};
+
+    /// The language of the syntax wrapped by a derived AST type, which also
+    /// works through type aliases.
+    pub trait HasLanguage {
+        type Language: Language;
+    }
+
+    impl<L: Language> HasLanguage for SyntaxNode<L> {
+        type Language = L;
+    }
+
+    impl<L: Language> HasLanguage for SyntaxToken<L> {
+        type Language = L;
+    }
+}
+
 pub mod support {
-    use super::{AstChildren, AstNode};
+    use super::{AstChildren, AstNode, AstToken, AstTokenChildren};
     use crate::{Language, SyntaxNode, SyntaxToken};
 
     pub fn child<N: AstNode>(parent: &SyntaxNode<N::Language>) -> Option<N> {
@@ -231,4 +432,97 @@ pub mod support {
             .filter_map(|it| it.into_token())
             .find(|it| it.kind() == kind)
     }
+
+    pub fn token_typed<T: AstToken>(parent: &SyntaxNode<T::Language>) -> Option<T> {
+        tokens(parent).next()
+    }
+
+    pub fn tokens<T: AstToken>(parent: &SyntaxNode<T::Language>) -> AstTokenChildren<T> {
+        AstTokenChildren::new(parent)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{test_utils::TestLang, GreenNodeBuilder, SyntaxKind};
+
+    use super::*;
+
+    #[test]
+    fn token_and_element_ptrs() {
+        // ROOT(RULE("rule" " " NAME("foo")))
+        let mut builder = GreenNodeBuilder::new();
+        builder.start_node(SyntaxKind(0));
+        builder.start_node(SyntaxKind(1));
+        builder.token(SyntaxKind(10), "rule");
+        builder.token(SyntaxKind(11), " ");
+        builder.start_node(SyntaxKind(2));
+        builder.token(SyntaxKind(12), "foo");
+        builder.finish_node();
+        builder.finish_node();
+        builder.finish_node();
+        let green = builder.finish();
+        let root = SyntaxNode::<TestLang>::new_root(green.clone());
+
+        let name = root.last_token().unwrap();
+        let ptr = SyntaxTokenPtr::new(&name);
+        let other_root = SyntaxNode::<TestLang>::new_root(green);
+        assert_eq!(ptr.to_token(&other_root), other_root.last_token().unwrap());
+        let rule = root.first_child().unwrap();
+        assert_eq!(ptr.try_to_token(&rule), None);
+
+        let token_ptr = SyntaxElementPtr::from(ptr);
+        assert_eq!(token_ptr.as_token_ptr(), Some(ptr));
+        assert_eq!(
+            token_ptr.to_element(&root),
+            NodeOrToken::Token(name.clone())
+        );
+        let name_node = name.parent().unwrap();
+        let node_ptr = SyntaxElementPtr::new(&name_node.clone().into());
+        assert_eq!(node_ptr.kind(), SyntaxKind(2));
+        assert_eq!(node_ptr.try_to_element(&root), Some(name_node.into()));
+        assert_ne!(node_ptr, token_ptr);
+    }
+
+    struct IntLiteral(SyntaxToken<TestLang>);
+
+    impl AstToken for IntLiteral {
+        type Language = TestLang;
+
+        fn can_cast(kind: SyntaxKind) -> bool {
+            kind == SyntaxKind(20)
+        }
+        fn cast(token: SyntaxToken<TestLang>) -> Option<Self> {
+            Self::can_cast(token.kind()).then_some(IntLiteral(token))
+        }
+        fn syntax(&self) -> &SyntaxToken<TestLang> {
+            &self.0
+        }
+    }
+
+    impl IntLiteral {
+        fn value(&self) -> Option<u64> {
+            self.text().trim_end_matches("kb").parse().ok()
+        }
+    }
+
+    #[test]
+    fn typed_tokens() {
+        // LIST("1" "," "2kb" NESTED("3"))
+        let mut builder = GreenNodeBuilder::new();
+        builder.start_node(SyntaxKind(0));
+        builder.token(SyntaxKind(20), "1");
+        builder.token(SyntaxKind(21), ",");
+        builder.token(SyntaxKind(20), "2kb");
+        builder.start_node(SyntaxKind(1));
+        builder.token(SyntaxKind(20), "3");
+        builder.finish_node();
+        builder.finish_node();
+        let list = SyntaxNode::<TestLang>::new_root(builder.finish());
+
+        let first = support::token_typed::<IntLiteral>(&list).unwrap();
+        assert_eq!(first.value(), Some(1));
+        let values = support::tokens::<IntLiteral>(&list).map(|it| it.value());
+        assert!(values.eq([Some(1), Some(2)]));
+    }
 }
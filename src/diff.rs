@@ -0,0 +1,344 @@
+//! Structural diff of two syntax trees.
+//!
+//! The diff is computed at the granularity of children lists: children of two
+//! matching nodes are aligned with a longest common subsequence, unmatched
+//! nodes of the same kind are diffed recursively, and everything else becomes
+//! a deletion, an insertion or a replacement. Identical subtrees are detected
+//! by comparing green nodes, which is usually a pointer comparison thanks to
+//! [`NodeCache`](crate::NodeCache) interning.
+
+use std::{iter, ptr};
+
+use rustc_hash::FxHashMap;
+
+use crate::{
+    green::GreenNodeData, red, GreenNode, Language, NodeOrToken, SyntaxElement, SyntaxNode,
+    SyntaxToken,
+};
+
+/// Children lists are aligned with an LCS only if the table, whose size is
+/// the product of the lengths of the unmatched middle parts, stays below this.
+const MAX_LCS_CELLS: usize = 1 << 20;
+
+/// A single step of a [`TreeDiff`]. Elements named `old` refer to the old
+/// tree, and elements named `new` to the new one.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum DiffOp<L: Language> {
+    /// Remove the element from its parent.
+    Delete(SyntaxElement<L>),
+    /// Replace `old` with `new`.
+    Replace {
+        old: SyntaxElement<L>,
+        new: SyntaxElement<L>,
+    },
+    /// Insert `new` into `parent`, right after `after`, or as the first
+    /// children if `after` is `None`. `after` may be an element replaced by
+    /// another op, in which case the insertion goes after the replacement.
+    Insert {
+        parent: SyntaxNode<L>,
+        after: Option<SyntaxElement<L>>,
+        new: Vec<SyntaxElement<L>>,
+    },
+}
+
+/// An edit script turning one tree into another, see [`diff`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TreeDiff<L: Language> {
+    ops: Vec<DiffOp<L>>,
+}
+
+/// Computes the edit script which turns `old` into `new`.
+pub fn diff<L: Language>(old: &SyntaxNode<L>, new: &SyntaxNode<L>) -> TreeDiff<L> {
+    let mut res = TreeDiff { ops: Vec::new() };
+    if green_eq(&old.green(), &new.green()) {
+        return res;
+    }
+    if old.kind() != new.kind() {
+        res.ops.push(DiffOp::Replace {
+            old: old.clone().into(),
+            new: new.clone().into(),
+        });
+        return res;
+    }
+    res.diff_children(old, new);
+    res
+}
+
+/// Like [`diff`], but for green trees. The elements in the script belong to
+/// fresh roots created for `old` and `new`.
+pub fn diff_green<L: Language>(old: &GreenNode, new: &GreenNode) -> TreeDiff<L> {
+    diff(
+        &SyntaxNode::new_root(old.clone()),
+        &SyntaxNode::new_root(new.clone()),
+    )
+}
+
+impl<L: Language> TreeDiff<L> {
+    pub fn ops(&self) -> &[DiffOp<L>] {
+        &self.ops
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+
+    /// Applies the script to `root`, a mutable tree equal to the old tree
+    /// (usually its `clone_for_update`), and returns the new root. The
+    /// returned root is `root` itself, unless the whole tree was replaced.
+    pub fn apply(&self, root: &SyntaxNode<L>) -> SyntaxNode<L> {
+        assert!(root.parent().is_none(), "not a root: {:?}", root);
+
+        // Resolve all the positions before touching anything: mutable cursors
+        // keep their indices up to date while siblings come and go.
+        let ops = self
+            .ops
+            .iter()
+            .map(|op| match op {
+                DiffOp::Delete(old) => DiffOp::Delete(resolve(root, old)),
+                DiffOp::Replace { old, new } => DiffOp::Replace {
+                    old: resolve(root, old),
+                    new: new.clone(),
+                },
+                DiffOp::Insert { parent, after, new } => DiffOp::Insert {
+                    parent: resolve(root, &parent.clone().into()).into_node().unwrap(),
+                    after: after.as_ref().map(|it| resolve(root, it)),
+                    new: new.clone(),
+                },
+            })
+            .collect::<Vec<_>>();
+
+        let mut replaced = FxHashMap::default();
+        for op in ops {
+            match op {
+                DiffOp::Delete(old) => old.detach(),
+                DiffOp::Replace { old, new } => {
+                    let new = detached_copy(&new);
+                    let parent = match old.parent() {
+                        Some(it) => it,
+                        None => return new.into_node().unwrap(),
+                    };
+                    let index = old.index();
+                    parent.splice_children(index..index + 1, vec![new.clone()]);
+                    replaced.insert(old, new);
+                }
+                DiffOp::Insert { parent, after, new } => {
+                    let after = after.map(|it| replaced.get(&it).cloned().unwrap_or(it));
+                    let index = after.map_or(0, |it| it.index() + 1);
+                    let new = new.iter().map(detached_copy).collect();
+                    parent.splice_children(index..index, new);
+                }
+            }
+        }
+        root.clone()
+    }
+
+    fn diff_children(&mut self, old: &SyntaxNode<L>, new: &SyntaxNode<L>) {
+        let old_children = old.children_with_tokens().collect::<Vec<_>>();
+        let new_children = new.children_with_tokens().collect::<Vec<_>>();
+        let eq = |i: usize, j: usize| element_eq(&old_children[i], &new_children[j]);
+        let (n, m) = (old_children.len(), new_children.len());
+
+        // Edits are usually local, so trim the common prefix and suffix before
+        // running the quadratic LCS.
+        let prefix = (0..n.min(m)).take_while(|&i| eq(i, i)).count();
+        let suffix = (0..(n - prefix).min(m - prefix))
+            .take_while(|&k| eq(n - 1 - k, m - 1 - k))
+            .count();
+        let (a, b) = (n - prefix - suffix, m - prefix - suffix);
+
+        let mut matches = (0..prefix).map(|i| (i, i)).collect::<Vec<_>>();
+        // The LCS table is quadratic in size. Past the limit, the remaining
+        // children are left unaligned and diffed pairwise, which still gives
+        // a correct, if longer, script.
+        if a.saturating_mul(b) <= MAX_LCS_CELLS {
+            // lcs[i][j] is the length of the LCS of old[i..a] and new[j..b].
+            let mut lcs = vec![0u32; (a + 1) * (b + 1)];
+            let at = |i: usize, j: usize| i * (b + 1) + j;
+            for i in (0..a).rev() {
+                for j in (0..b).rev() {
+                    lcs[at(i, j)] = if eq(prefix + i, prefix + j) {
+                        lcs[at(i + 1, j + 1)] + 1
+                    } else {
+                        lcs[at(i + 1, j)].max(lcs[at(i, j + 1)])
+                    };
+                }
+            }
+
+            let (mut i, mut j) = (0, 0);
+            while i < a && j < b {
+                if eq(prefix + i, prefix + j) {
+                    matches.push((prefix + i, prefix + j));
+                    i += 1;
+                    j += 1;
+                } else if lcs[at(i + 1, j)] >= lcs[at(i, j + 1)] {
+                    i += 1;
+                } else {
+                    j += 1;
+                }
+            }
+        }
+        matches.extend((0..suffix).rev().map(|k| (n - 1 - k, m - 1 - k)));
+
+        let mut anchor = None;
+        let (mut i, mut j) = (0, 0);
+        for (mi, mj) in matches.into_iter().chain(iter::once((n, m))) {
+            self.diff_gap(
+                old,
+                &old_children[i..mi],
+                &new_children[j..mj],
+                anchor.take(),
+            );
+            anchor = old_children.get(mi).cloned();
+            i = mi + 1;
+            j = mj + 1;
+        }
+    }
+
+    /// Diffs a run of unmatched children, `after` is the matched old child
+    /// right before the run.
+    fn diff_gap(
+        &mut self,
+        parent: &SyntaxNode<L>,
+        old: &[SyntaxElement<L>],
+        new: &[SyntaxElement<L>],
+        mut after: Option<SyntaxElement<L>>,
+    ) {
+        let paired = old.len().min(new.len());
+        for (old, new) in old.iter().zip(new) {
+            match (old, new) {
+                (NodeOrToken::Node(old), NodeOrToken::Node(new)) if old.kind() == new.kind() => {
+                    self.diff_children(old, new)
+                }
+                _ => self.ops.push(DiffOp::Replace {
+                    old: old.clone(),
+                    new: new.clone(),
+                }),
+            }
+            after = Some(old.clone());
+        }
+        for old in &old[paired..] {
+            self.ops.push(DiffOp::Delete(old.clone()));
+        }
+        if new.len() > paired {
+            self.ops.push(DiffOp::Insert {
+                parent: parent.clone(),
+                after,
+                new: new[paired..].to_vec(),
+            });
+        }
+    }
+}
+
+fn green_eq(lhs: &GreenNodeData, rhs: &GreenNodeData) -> bool {
+    ptr::eq(lhs, rhs)
+        || (lhs.kind() == rhs.kind()
+            && lhs.text_len() == rhs.text_len()
+            && lhs.children().len() == rhs.children().len()
+            && lhs.children().zip(rhs.children()).all(|pair| match pair {
+                (NodeOrToken::Node(lhs), NodeOrToken::Node(rhs)) => green_eq(lhs, rhs),
+                (NodeOrToken::Token(lhs), NodeOrToken::Token(rhs)) => {
+                    ptr::eq(lhs, rhs) || lhs == rhs
+                }
+                _ => false,
+            }))
+}
+
+fn element_eq<L: Language>(lhs: &SyntaxElement<L>, rhs: &SyntaxElement<L>) -> bool {
+    match (lhs, rhs) {
+        (NodeOrToken::Node(lhs), NodeOrToken::Node(rhs)) => green_eq(&lhs.green(), &rhs.green()),
+        (NodeOrToken::Token(lhs), NodeOrToken::Token(rhs)) => {
+            ptr::eq(lhs.green(), rhs.green()) || lhs.green() == rhs.green()
+        }
+        _ => false,
+    }
+}
+
+/// Finds the element at the same position as `element` in `root`.
+fn resolve<L: Language>(root: &SyntaxNode<L>, element: &SyntaxElement<L>) -> SyntaxElement<L> {
+    let mut path = iter::successors(Some(element.clone()), |it| it.parent().map(Into::into))
+        .map(|it| it.index())
+        .collect::<Vec<_>>();
+    path.pop();
+
+    let mut res: red::SyntaxElement = red::SyntaxNode::from(root.clone()).into();
+    for index in path.into_iter().rev() {
+        res = res
+            .into_node()
+            .and_then(|it| it.child_or_token_at_index(index))
+            .unwrap_or_else(|| panic!("tree doesn't match the diff: {:?}", root));
+    }
+    res.into()
+}
+
+/// Returns a mutable, parentless copy of `element`, ready to be attached.
+fn detached_copy<L: Language>(element: &SyntaxElement<L>) -> SyntaxElement<L> {
+    match element {
+        NodeOrToken::Node(it) => it.clone_subtree().clone_for_update().into(),
+        NodeOrToken::Token(it) => {
+            SyntaxToken::from(red::SyntaxToken::new_detached(it.green().to_owned())).into()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{test_utils::TestLang, GreenNodeBuilder, SyntaxKind};
+
+    use super::*;
+
+    /// `a(b c)d` => ROOT(a NODE(b c) d)
+    fn parse(text: &str) -> SyntaxNode<TestLang> {
+        let mut builder = GreenNodeBuilder::new();
+        builder.start_node(SyntaxKind(0));
+        for c in text.chars() {
+            match c {
+                '(' => builder.start_node(SyntaxKind(1)),
+                ')' => builder.finish_node(),
+                _ => builder.token(SyntaxKind(2 + c.is_ascii_digit() as u16), &c.to_string()),
+            }
+        }
+        builder.finish_node();
+        SyntaxNode::new_root(builder.finish())
+    }
+
+    #[test]
+    fn diff_apply_yields_new_tree() {
+        fn check(old: &str, new: &str) {
+            let (old, new) = (parse(old), parse(new));
+            let diff = diff(&old, &new);
+            let res = diff.apply(&old.clone_for_update());
+            assert_eq!(*res.green(), *new.green(), "{:?}", diff.ops());
+        }
+
+        check("ab(cd)e", "ab(cd)e");
+        check("ab(cd)e", "ab(cxd)e");
+        check("ab(cd)e", "a1(cd)");
+        check("ab(cd)e", "(cd)xyab");
+        check("(a)(b)", "x(a)(c)y(z)");
+        check("abc", "");
+    }
+
+    #[test]
+    fn diff_wide_nodes_pairwise() {
+        // Too wide for the LCS table, the middle is diffed pairwise.
+        let wide = |c: &str| parse(&format!("x{}y", c.repeat(1100)));
+        let ops = diff(&wide("a"), &wide("b")).ops;
+        assert_eq!(ops.len(), 1100);
+        assert!(ops.iter().all(|op| matches!(op, DiffOp::Replace { .. })));
+    }
+
+    #[test]
+    fn diff_skips_shared_subtrees() {
+        let old = parse("(ab)(cd)");
+        let new = parse("(ab)(cx)");
+        let ops = diff(&old, &new).ops;
+        assert_eq!(ops.len(), 1);
+        match &ops[0] {
+            DiffOp::Replace { old, new } => {
+                assert_eq!(old.to_string(), "d");
+                assert_eq!(new.to_string(), "x");
+            }
+            op => panic!("unexpected op: {:?}", op),
+        }
+    }
+}
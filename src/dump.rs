@@ -0,0 +1,465 @@
+//! Indented textual dumps of syntax trees, one element per line:
+//!
+//! ```text
+//! ROOT@0..5
+//!   IDENT@0..3 "foo"
+//!   WHITESPACE@3..5 "  "
+//! ```
+//!
+//! Children are indented by two spaces and token texts are escaped like
+//! `{:?}` of a `str`. The format only depends on the tree, so dumps can be
+//! used as golden test snapshots.
+
+use std::{
+    error::Error,
+    fmt::{self, Write},
+};
+
+use crate::{
+    green::GreenNodeData, red, GreenNode, GreenNodeBuilder, Language, NodeOrToken, SyntaxKind,
+    SyntaxText, TextRange, TextSize, WalkEvent,
+};
+
+/// Names a raw kind via [`Language::kind_name`], falling back to the `Debug`
+/// representation of `L::Kind`.
+pub fn kind_to_string<L: Language>(raw: SyntaxKind) -> String {
+    let kind = L::kind_from_raw(raw);
+    match L::kind_name(kind) {
+        Some(name) => name.to_string(),
+        None => format!("{:?}", kind),
+    }
+}
+
+struct Dumper<F> {
+    buf: String,
+    kind_name: F,
+}
+
+impl<F: Fn(SyntaxKind) -> String> Dumper<F> {
+    fn new(kind_name: F) -> Dumper<F> {
+        Dumper {
+            buf: String::new(),
+            kind_name,
+        }
+    }
+
+    fn node(&mut self, depth: usize, kind: SyntaxKind, range: TextRange) {
+        self.indent(depth);
+        writeln!(self.buf, "{}@{:?}", (self.kind_name)(kind), range).unwrap();
+    }
+
+    fn token(&mut self, depth: usize, kind: SyntaxKind, range: TextRange, text: &str) {
+        self.indent(depth);
+        writeln!(
+            self.buf,
+            "{}@{:?} {:?}",
+            (self.kind_name)(kind),
+            range,
+            text
+        )
+        .unwrap();
+    }
+
+    fn indent(&mut self, depth: usize) {
+        for _ in 0..depth {
+            self.buf.push_str("  ");
+        }
+    }
+}
+
+impl GreenNodeData {
+    /// Dumps the tree, with offsets relative to the start of this node.
+    pub fn dump<F: Fn(SyntaxKind) -> String>(&self, kind_name: F) -> String {
+        let mut dumper = Dumper::new(kind_name);
+        dumper.node(0, self.kind(), TextRange::up_to(self.text_len()));
+        let mut stack = vec![(self.children(), TextSize::from(0))];
+        while let Some((children, offset)) = stack.last_mut() {
+            let child = match children.next() {
+                Some(it) => it,
+                None => {
+                    stack.pop();
+                    continue;
+                }
+            };
+            let range = TextRange::at(*offset, child.text_len());
+            *offset = range.end();
+            let depth = stack.len();
+            match child {
+                NodeOrToken::Node(node) => {
+                    dumper.node(depth, node.kind(), range);
+                    stack.push((node.children(), range.start()));
+                }
+                NodeOrToken::Token(token) => dumper.token(depth, token.kind(), range, token.text()),
+            }
+        }
+        dumper.buf
+    }
+}
+
+impl red::SyntaxNode {
+    /// Dumps the subtree, with offsets relative to the root of the tree.
+    pub fn dump<F: Fn(SyntaxKind) -> String>(&self, kind_name: F) -> String {
+        let mut dumper = Dumper::new(kind_name);
+        let mut depth = 0;
+        for event in self.preorder_with_tokens() {
+            match event {
+                WalkEvent::Enter(NodeOrToken::Node(node)) => {
+                    dumper.node(depth, node.kind(), node.text_range());
+                    depth += 1;
+                }
+                WalkEvent::Enter(NodeOrToken::Token(token)) => {
+                    dumper.token(depth, token.kind(), token.text_range(), token.text())
+                }
+                WalkEvent::Leave(NodeOrToken::Node(_)) => depth -= 1,
+                WalkEvent::Leave(NodeOrToken::Token(_)) => (),
+            }
+        }
+        dumper.buf
+    }
+}
+
+impl SyntaxText {
+    /// Dumps the elements overlapping this text, with their ranges and texts
+    /// clipped to it.
+    pub fn dump<F: Fn(SyntaxKind) -> String>(&self, kind_name: F) -> String {
+        let text_range = self.range();
+        let clip = |range: TextRange| {
+            let clipped = text_range.intersect(range)?;
+            if clipped.is_empty() && !range.is_empty() {
+                return None;
+            }
+            Some(clipped)
+        };
+
+        let mut dumper = Dumper::new(kind_name);
+        let mut depth = 0;
+        for event in self.node().preorder_with_tokens() {
+            match event {
+                WalkEvent::Enter(NodeOrToken::Node(node)) => {
+                    if let Some(range) = clip(node.text_range()) {
+                        dumper.node(depth, node.kind(), range);
+                    }
+                    depth += 1;
+                }
+                WalkEvent::Enter(NodeOrToken::Token(token)) => {
+                    if let Some(range) = clip(token.text_range()) {
+                        let text = &token.text()[range - token.text_range().start()];
+                        dumper.token(depth, token.kind(), range, text)
+                    }
+                }
+                WalkEvent::Leave(NodeOrToken::Node(_)) => depth -= 1,
+                WalkEvent::Leave(NodeOrToken::Token(_)) => (),
+            }
+        }
+        dumper.buf
+    }
+}
+
+/// An error in a tree dump given to [`parse_dump`]. Lines are 1-based.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DumpError {
+    /// The dump contains no elements.
+    Empty,
+    /// The line is not of the form `KIND@start..end` or `KIND@start..end "text"`.
+    Malformed {
+        line: usize,
+    },
+    UnknownKind {
+        line: usize,
+        name: String,
+    },
+    /// The line is not indented by two spaces per level, is nested under a
+    /// token, or is a second root.
+    BadIndent {
+        line: usize,
+    },
+    /// The root is a token.
+    TokenRoot {
+        line: usize,
+    },
+    /// The range on the line disagrees with the texts of the preceding tokens.
+    RangeMismatch {
+        line: usize,
+        expected: TextRange,
+        found: TextRange,
+    },
+}
+
+impl fmt::Display for DumpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DumpError::Empty => f.write_str("empty tree dump"),
+            DumpError::Malformed { line } => write!(f, "line {line}: malformed element"),
+            DumpError::UnknownKind { line, name } => {
+                write!(f, "line {line}: unknown kind `{name}`")
+            }
+            DumpError::BadIndent { line } => write!(f, "line {line}: bad indentation"),
+            DumpError::TokenRoot { line } => write!(f, "line {line}: root must be a node"),
+            DumpError::RangeMismatch {
+                line,
+                expected,
+                found,
+            } => {
+                write!(
+                    f,
+                    "line {line}: expected range {expected:?}, found {found:?}"
+                )
+            }
+        }
+    }
+}
+
+impl Error for DumpError {}
+
+/// Parses a dump in the format produced by [`GreenNodeData::dump`] back into
+/// a tree, resolving kind names with `resolve`.
+///
+/// The root may start at a non-zero offset, as in dumps of subtrees. Every
+/// range must agree with the texts of the tokens before it. Blank lines are
+/// ignored.
+pub fn parse_dump<F>(dump: &str, resolve: F) -> Result<GreenNode, DumpError>
+where
+    F: Fn(&str) -> Option<SyntaxKind>,
+{
+    let mut builder = GreenNodeBuilder::new();
+    // Line numbers and ranges of the nodes that are still open.
+    let mut open: Vec<(usize, TextRange)> = Vec::new();
+    let mut offset = None;
+
+    let close = |open: &mut Vec<(usize, TextRange)>, builder: &mut GreenNodeBuilder, offset| {
+        let (line, range) = open.pop().unwrap();
+        if range.end() != offset {
+            let expected = TextRange::new(range.start(), offset);
+            return Err(DumpError::RangeMismatch {
+                line,
+                expected,
+                found: range,
+            });
+        }
+        builder.finish_node();
+        Ok(())
+    };
+
+    for (idx, text) in dump.lines().enumerate() {
+        let line = idx + 1;
+        if text.trim().is_empty() {
+            continue;
+        }
+        let content = text.trim_start_matches(' ');
+        let indent = text.len() - content.len();
+        let second_root = indent == 0 && offset.is_some();
+        if indent % 2 != 0 || indent / 2 > open.len() || second_root {
+            return Err(DumpError::BadIndent { line });
+        }
+        while open.len() > indent / 2 {
+            close(&mut open, &mut builder, offset.unwrap())?;
+        }
+
+        let element = parse_line(content).ok_or(DumpError::Malformed { line })?;
+        let kind = resolve(element.name).ok_or_else(|| DumpError::UnknownKind {
+            line,
+            name: element.name.to_string(),
+        })?;
+        let start = *offset.get_or_insert(element.range.start());
+        let expected = match &element.text {
+            Some(text) => {
+                // A token ending past `u32::MAX` can't come from a real tree.
+                let end = start.checked_add(TextSize::of(text.as_str()));
+                TextRange::new(start, end.ok_or(DumpError::Malformed { line })?)
+            }
+            None => TextRange::new(start, element.range.end().max(start)),
+        };
+        if element.range != expected {
+            return Err(DumpError::RangeMismatch {
+                line,
+                expected,
+                found: element.range,
+            });
+        }
+        match element.text {
+            Some(text) => {
+                if open.is_empty() {
+                    return Err(DumpError::TokenRoot { line });
+                }
+                builder.token(kind, &text);
+                offset = Some(expected.end());
+            }
+            None => {
+                builder.start_node(kind);
+                open.push((line, element.range));
+            }
+        }
+    }
+
+    let offset = offset.ok_or(DumpError::Empty)?;
+    while !open.is_empty() {
+        close(&mut open, &mut builder, offset)?;
+    }
+    Ok(builder.finish())
+}
+
+struct DumpLine<'a> {
+    name: &'a str,
+    range: TextRange,
+    text: Option<String>,
+}
+
+/// Parses `KIND@start..end` or `KIND@start..end "text"`.
+fn parse_line(line: &str) -> Option<DumpLine<'_>> {
+    let (name, rest) = line.split_once('@')?;
+    let (range, text) = match rest.split_once(' ') {
+        Some((range, text)) => (range, Some(unescape(text)?)),
+        None => (rest, None),
+    };
+    let (start, end) = range.split_once("..")?;
+    let start = TextSize::from(start.parse::<u32>().ok()?);
+    let end = TextSize::from(end.parse::<u32>().ok()?);
+    if name.is_empty() || start > end {
+        return None;
+    }
+    Some(DumpLine {
+        name,
+        range: TextRange::new(start, end),
+        text,
+    })
+}
+
+/// Reverses the `Debug` escaping of a string literal.
+fn unescape(literal: &str) -> Option<String> {
+    let mut chars = literal.strip_prefix('"')?.strip_suffix('"')?.chars();
+    let mut res = String::new();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => return None,
+            '\\' => {}
+            _ => {
+                res.push(c);
+                continue;
+            }
+        }
+        let c = match chars.next()? {
+            'n' => '\n',
+            'r' => '\r',
+            't' => '\t',
+            '0' => '\0',
+            c @ ('\\' | '"' | '\'') => c,
+            'u' => {
+                let rest = chars.as_str().strip_prefix('{')?;
+                let (hex, rest) = rest.split_once('}')?;
+                chars = rest.chars();
+                char::from_u32(u32::from_str_radix(hex, 16).ok()?)?
+            }
+            _ => return None,
+        };
+        res.push(c);
+    }
+    Some(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{test_utils::TestLang, GreenNodeBuilder, SyntaxNode};
+
+    use super::*;
+
+    #[test]
+    fn dumps() {
+        let mut builder = GreenNodeBuilder::new();
+        builder.start_node(SyntaxKind(0));
+        builder.token(SyntaxKind(2), "f");
+        builder.start_node(SyntaxKind(1));
+        builder.token(SyntaxKind(3), "(\"x\")");
+        builder.finish_node();
+        builder.finish_node();
+        let green = builder.finish();
+        let name = |kind: SyntaxKind| match ["ROOT", "CALL", "IDENT"].get(kind.0 as usize) {
+            Some(it) => it.to_string(),
+            None => kind_to_string::<TestLang>(kind),
+        };
+
+        let expected = r#"ROOT@0..6
+  IDENT@0..1 "f"
+  CALL@1..6
+    SyntaxKind(3)@1..6 "(\"x\")"
+"#;
+        assert_eq!(green.dump(name), expected);
+        let root = SyntaxNode::<TestLang>::new_root(green.clone());
+        assert_eq!(root.dump(), green.dump(kind_to_string::<TestLang>));
+        assert!(root.dump().starts_with("SyntaxKind(0)@0..6\n"));
+        assert_eq!(format!("{:#?}", root).lines().count(), 4);
+
+        let text = root.text().slice(TextSize::from(3)..);
+        let expected = r#"ROOT@3..6
+  CALL@3..6
+    SyntaxKind(3)@3..6 "x\")"
+"#;
+        assert_eq!(text.dump(name), expected);
+    }
+
+    #[test]
+    fn parses_dumps() {
+        let resolve = |name: &str| match name {
+            "ROOT" => Some(SyntaxKind(0)),
+            "IDENT" => Some(SyntaxKind(2)),
+            _ => name.strip_prefix('K')?.parse().ok().map(SyntaxKind),
+        };
+        let dump = r#"ROOT@2..10
+  IDENT@2..3 "f"
+  K1@3..10
+    K3@3..8 "(\"\u{e9}\n"
+    K1@8..8
+    IDENT@8..10 "\t'"
+"#;
+        let green = parse_dump(dump, resolve).unwrap();
+        assert_eq!(green.to_string(), "f(\"\u{e9}\n\t'");
+        let kind_name = |kind: SyntaxKind| match kind.0 {
+            0 => "ROOT".to_string(),
+            2 => "IDENT".to_string(),
+            n => format!("K{n}"),
+        };
+        assert_eq!(parse_dump(&green.dump(kind_name), resolve).unwrap(), green,);
+
+        let err = |dump: &str| parse_dump(dump, resolve).unwrap_err();
+        assert_eq!(err(""), DumpError::Empty);
+        assert_eq!(
+            err("ROOT@0..1\n   IDENT@0..1 \"x\""),
+            DumpError::BadIndent { line: 2 }
+        );
+        assert_eq!(
+            err("ROOT@0..1\nROOT@1..1"),
+            DumpError::BadIndent { line: 2 }
+        );
+        assert_eq!(err("IDENT@0..1 \"x\""), DumpError::TokenRoot { line: 1 });
+        assert_eq!(
+            err("ROOT@0..1\n  FOO@0..1"),
+            DumpError::UnknownKind {
+                line: 2,
+                name: "FOO".into()
+            }
+        );
+        assert_eq!(
+            err("ROOT@0..1\n  IDENT@0..1 x"),
+            DumpError::Malformed { line: 2 }
+        );
+        assert_eq!(
+            err("ROOT@4294967295..4294967295\n  IDENT@4294967295..4294967295 \"x\""),
+            DumpError::Malformed { line: 2 }
+        );
+        assert_eq!(
+            err("ROOT@0..3\n  IDENT@0..2 \"x\""),
+            DumpError::RangeMismatch {
+                line: 2,
+                expected: TextRange::new(0.into(), 1.into()),
+                found: TextRange::new(0.into(), 2.into()),
+            }
+        );
+        assert_eq!(
+            err("ROOT@0..3\n  IDENT@0..1 \"x\""),
+            DumpError::RangeMismatch {
+                line: 1,
+                expected: TextRange::new(0.into(), 1.into()),
+                found: TextRange::new(0.into(), 3.into()),
+            }
+        );
+    }
+}
@@ -1,19 +1,34 @@
+mod binary;
 mod builder;
+mod content_hash;
 mod element;
+mod intern_policy;
+mod memory;
 mod node;
 mod node_cache;
+mod relex;
+mod reparse;
+mod shared_cache;
 mod token;
+mod walk;
 
 use self::element::GreenElement;
 
 pub(crate) use self::{element::GreenElementRef, node::GreenChild};
 
 pub use self::{
+    binary::DecodeError,
     builder::{Checkpoint, GreenNodeBuilder},
+    intern_policy::InternPolicy,
+    memory::{AllocCounts, MemoryReport},
     node::{Children, GreenNode, GreenNodeData},
-    node_cache::NodeCache,
+    node_cache::{NodeCache, NodeCacheStats},
+    relex::RelexError,
+    shared_cache::SharedNodeCache,
     token::{GreenToken, GreenTokenData},
+    walk::{GreenAt, GreenPreorder},
 };
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SyntaxKind(pub u16);
@@ -0,0 +1,412 @@
+//! Compact binary encoding of green trees.
+//!
+//! The layout is (every integer is an unsigned LEB128 varint):
+//!
+//! ```text
+//! magic    b"RWGN"
+//! version
+//! strings  count, (len, utf-8 bytes)*
+//! tokens   count, (kind, string index)*
+//! nodes    count, (kind, n_children, child*)*
+//! ```
+//!
+//! A child is stored as `index << 1` for a node and `index << 1 | 1` for a
+//! token. Nodes are written in post-order, so children always refer to earlier
+//! entries and the last node is the root. Identical token texts, tokens and
+//! subtrees are written only once.
+
+use std::{error::Error, fmt, ptr, str};
+
+use rustc_hash::FxHashMap;
+
+use crate::{
+    green::{GreenElement, GreenNode, GreenNodeData, GreenToken, GreenTokenData, SyntaxKind},
+    NodeCache, NodeOrToken, TextSize,
+};
+
+const MAGIC: &[u8; 4] = b"RWGN";
+const VERSION: u64 = 1;
+
+/// An error returned when decoding a green tree from its binary encoding.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The input doesn't start with the expected magic bytes.
+    BadMagic,
+    /// The input was written with an unsupported format version.
+    UnsupportedVersion(u64),
+    /// The input ended in the middle of the tree.
+    UnexpectedEof,
+    /// The input is structurally invalid, e.g. refers to a missing entry.
+    Malformed(&'static str),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::BadMagic => write!(f, "not a green tree: bad magic"),
+            DecodeError::UnsupportedVersion(version) => {
+                write!(f, "unsupported green tree format version {}", version)
+            }
+            DecodeError::UnexpectedEof => write!(f, "unexpected end of input"),
+            DecodeError::Malformed(msg) => write!(f, "malformed green tree: {}", msg),
+        }
+    }
+}
+
+impl Error for DecodeError {}
+
+impl GreenNodeData {
+    /// Encodes this tree into the compact binary format. Use
+    /// [`GreenNode::decode`] or [`NodeCache::decode_node`] to read it back.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut encoder = Encoder::default();
+        encoder.node(self);
+        encoder.finish()
+    }
+}
+
+impl GreenNode {
+    /// Decodes a tree written by [`GreenNodeData::encode`], using a fresh
+    /// [`NodeCache`].
+    pub fn decode(bytes: &[u8]) -> Result<GreenNode, DecodeError> {
+        NodeCache::default().decode_node(bytes)
+    }
+}
+
+impl NodeCache {
+    /// Decodes a tree written by [`GreenNodeData::encode`], interning all of
+    /// its nodes and tokens in this cache, and then enforcing its size limit.
+    pub fn decode_node(&mut self, bytes: &[u8]) -> Result<GreenNode, DecodeError> {
+        let mut reader = Reader { bytes };
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err(DecodeError::BadMagic);
+        }
+        let version = reader.varint()?;
+        if version != VERSION {
+            return Err(DecodeError::UnsupportedVersion(version));
+        }
+
+        let n_strings = reader.len()?;
+        let mut strings = Vec::with_capacity(n_strings);
+        for _ in 0..n_strings {
+            let len = reader.len()?;
+            let text = str::from_utf8(reader.take(len)?)
+                .map_err(|_| DecodeError::Malformed("token text is not utf-8"))?;
+            strings.push(text);
+        }
+
+        let n_tokens = reader.len()?;
+        let mut tokens: Vec<(u64, GreenToken)> = Vec::with_capacity(n_tokens);
+        for _ in 0..n_tokens {
+            let kind = reader.kind()?;
+            let text = reader.index(&strings)?;
+            tokens.push(self.token(kind, text));
+        }
+
+        let n_nodes = reader.len()?;
+        let mut nodes: Vec<(u64, GreenNode)> = Vec::with_capacity(n_nodes);
+        let mut children: Vec<(u64, GreenElement)> = Vec::new();
+        for _ in 0..n_nodes {
+            let kind = reader.kind()?;
+            let n_children = reader.len()?;
+            // Shared subtrees make the text grow exponentially with the size
+            // of the input, so it may not fit into a `TextSize`.
+            let mut text_len = TextSize::from(0);
+            for _ in 0..n_children {
+                let child = reader.varint()?;
+                let child: (u64, GreenElement) = match child & 1 {
+                    0 => {
+                        let (hash, node) = index(&nodes, child >> 1)?;
+                        (*hash, node.clone().into())
+                    }
+                    _ => {
+                        let (hash, token) = index(&tokens, child >> 1)?;
+                        (*hash, token.clone().into())
+                    }
+                };
+                text_len = text_len
+                    .checked_add(child.1.text_len())
+                    .ok_or(DecodeError::Malformed("tree is too large"))?;
+                children.push(child);
+            }
+            nodes.push(self.node(kind, &mut children, 0));
+        }
+
+        if !reader.bytes.is_empty() {
+            return Err(DecodeError::Malformed("trailing data"));
+        }
+        let root = match nodes.pop() {
+            Some((_, root)) => root,
+            None => return Err(DecodeError::Malformed("no root node")),
+        };
+        drop((tokens, nodes));
+        self.enforce_size_limit();
+        Ok(root)
+    }
+}
+
+#[derive(Default)]
+struct Encoder<'a> {
+    string_ids: FxHashMap<&'a str, u64>,
+    strings: Vec<&'a str>,
+    token_ids: FxHashMap<(SyntaxKind, u64), u64>,
+    tokens: Vec<(SyntaxKind, u64)>,
+    /// Shortcut for subtrees shared through `NodeCache`, which are already
+    /// known to be identical.
+    node_ptrs: FxHashMap<*const GreenNodeData, u64>,
+    node_ids: FxHashMap<(SyntaxKind, Vec<u64>), u64>,
+    nodes: Vec<(SyntaxKind, Vec<u64>)>,
+}
+
+impl<'a> Encoder<'a> {
+    fn token(&mut self, token: &'a GreenTokenData) -> u64 {
+        let text = *self.string_ids.entry(token.text()).or_insert_with(|| {
+            self.strings.push(token.text());
+            self.strings.len() as u64 - 1
+        });
+        *self
+            .token_ids
+            .entry((token.kind(), text))
+            .or_insert_with(|| {
+                self.tokens.push((token.kind(), text));
+                self.tokens.len() as u64 - 1
+            })
+    }
+
+    /// Adds the nodes of the tree in post-order, returning the id of `root`.
+    fn node(&mut self, root: &'a GreenNodeData) -> u64 {
+        // Nodes whose children are being added, with the ids of the children
+        // added so far.
+        let mut stack = vec![(root, root.children(), Vec::new())];
+        loop {
+            let (_, children, ids) = stack.last_mut().unwrap();
+            match children.next() {
+                Some(NodeOrToken::Node(it)) => match self.node_ptrs.get(&ptr::addr_of!(*it)) {
+                    Some(&id) => ids.push(id << 1),
+                    None => stack.push((it, it.children(), Vec::new())),
+                },
+                Some(NodeOrToken::Token(it)) => {
+                    let id = self.token(it);
+                    ids.push(id << 1 | 1);
+                }
+                None => {
+                    let (node, _, ids) = stack.pop().unwrap();
+                    let key = (node.kind(), ids);
+                    let id = match self.node_ids.get(&key) {
+                        Some(&id) => id,
+                        None => {
+                            let id = self.nodes.len() as u64;
+                            self.nodes.push(key.clone());
+                            self.node_ids.insert(key, id);
+                            id
+                        }
+                    };
+                    self.node_ptrs.insert(node, id);
+                    match stack.last_mut() {
+                        Some((_, _, ids)) => ids.push(id << 1),
+                        None => return id,
+                    }
+                }
+            }
+        }
+    }
+
+    fn finish(self) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+        write_varint(&mut out, VERSION);
+
+        write_varint(&mut out, self.strings.len() as u64);
+        for text in &self.strings {
+            write_varint(&mut out, text.len() as u64);
+            out.extend_from_slice(text.as_bytes());
+        }
+
+        write_varint(&mut out, self.tokens.len() as u64);
+        for &(kind, text) in &self.tokens {
+            write_varint(&mut out, kind.0.into());
+            write_varint(&mut out, text);
+        }
+
+        write_varint(&mut out, self.nodes.len() as u64);
+        for (kind, children) in &self.nodes {
+            write_varint(&mut out, kind.0.into());
+            write_varint(&mut out, children.len() as u64);
+            for &child in children {
+                write_varint(&mut out, child);
+            }
+        }
+        out
+    }
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn index<T>(items: &[T], idx: u64) -> Result<&T, DecodeError> {
+    usize::try_from(idx)
+        .ok()
+        .and_then(|idx| items.get(idx))
+        .ok_or(DecodeError::Malformed("reference to a missing entry"))
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], DecodeError> {
+        if self.bytes.len() < n {
+            return Err(DecodeError::UnexpectedEof);
+        }
+        let (head, tail) = self.bytes.split_at(n);
+        self.bytes = tail;
+        Ok(head)
+    }
+
+    fn varint(&mut self) -> Result<u64, DecodeError> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.take(1)?[0];
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(DecodeError::Malformed("varint is too long"))
+    }
+
+    /// Reads a length or a count. Every counted item takes at least one byte,
+    /// so anything longer than the rest of the input is invalid, and it's
+    /// safe to preallocate.
+    fn len(&mut self) -> Result<usize, DecodeError> {
+        match usize::try_from(self.varint()?) {
+            Ok(len) if len <= self.bytes.len() => Ok(len),
+            _ => Err(DecodeError::UnexpectedEof),
+        }
+    }
+
+    fn kind(&mut self) -> Result<SyntaxKind, DecodeError> {
+        u16::try_from(self.varint()?)
+            .map(SyntaxKind)
+            .map_err(|_| DecodeError::Malformed("kind is out of range"))
+    }
+
+    fn index<T: Copy>(&mut self, items: &[T]) -> Result<T, DecodeError> {
+        let idx = self.varint()?;
+        index(items, idx).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use crate::{test_utils::drop_deep, GreenNodeBuilder};
+
+    use super::*;
+
+    #[test]
+    fn binary_roundtrip() {
+        let mut builder = GreenNodeBuilder::new();
+        builder.start_node(SyntaxKind(0));
+        for _ in 0..5 {
+            builder.start_node(SyntaxKind(1));
+            builder.token(SyntaxKind(2), "$a");
+            builder.token(SyntaxKind(3), " ");
+            builder.token(SyntaxKind(2), "$a");
+            builder.token(SyntaxKind(4), "and");
+            builder.token(SyntaxKind(2), "$b");
+            builder.finish_node();
+        }
+        builder.finish_node();
+        let tree = builder.finish();
+
+        let bytes = tree.encode();
+        assert!(bytes.len() < tree.to_string().len());
+
+        let decoded = GreenNode::decode(&bytes).unwrap();
+        assert_eq!(tree, decoded);
+        let mut children = decoded.children();
+        let first = children.next().unwrap().into_node().unwrap();
+        assert!(children.all(|it| std::ptr::eq(it.into_node().unwrap(), first)));
+    }
+
+    #[test]
+    fn decode_errors() {
+        let mut builder = GreenNodeBuilder::new();
+        builder.start_node(SyntaxKind(0));
+        builder.token(SyntaxKind(1), "rule");
+        builder.finish_node();
+        let bytes = builder.finish().encode();
+
+        assert_eq!(GreenNode::decode(b"RWGT"), Err(DecodeError::BadMagic));
+        let mut version = bytes.clone();
+        version[MAGIC.len()] = 2;
+        assert_eq!(
+            GreenNode::decode(&version),
+            Err(DecodeError::UnsupportedVersion(2))
+        );
+        for len in 0..bytes.len() {
+            assert!(GreenNode::decode(&bytes[..len]).is_err());
+        }
+        assert_eq!(
+            GreenNode::decode(&bytes[..bytes.len() - 1]),
+            Err(DecodeError::UnexpectedEof)
+        );
+        let mut trailing = bytes;
+        trailing.push(0);
+        assert_eq!(
+            GreenNode::decode(&trailing),
+            Err(DecodeError::Malformed("trailing data"))
+        );
+    }
+
+    #[test]
+    fn decode_rejects_huge_trees() {
+        // Every node holds the previous one twice, doubling the text length.
+        let mut bytes = MAGIC.to_vec();
+        for it in [VERSION, 1, 1, b'a'.into(), 1, 0, 0, 40, 0, 1, 1] {
+            write_varint(&mut bytes, it);
+        }
+        for i in 1..40 {
+            for it in [0, 2, (i - 1) << 1, (i - 1) << 1] {
+                write_varint(&mut bytes, it);
+            }
+        }
+        assert_eq!(
+            GreenNode::decode(&bytes),
+            Err(DecodeError::Malformed("tree is too large"))
+        );
+    }
+
+    #[test]
+    fn deep_tree() {
+        let mut tree = GreenNode::new(SyntaxKind(1), [GreenToken::new(SyntaxKind(2), "a").into()]);
+        for _ in 0..10_000 {
+            tree = GreenNode::new(SyntaxKind(0), [tree.into()]);
+        }
+        // Small enough for recursing over the tree to overflow.
+        let stack_size = 256 * 1024;
+        let decoded = thread::scope(|s| {
+            let walk = || {
+                let bytes = tree.encode();
+                let decoded = GreenNode::decode(&bytes).unwrap();
+                assert_eq!(decoded.encode(), bytes);
+                decoded
+            };
+            let thread = thread::Builder::new().stack_size(stack_size);
+            thread.spawn_scoped(s, walk).unwrap().join().unwrap()
+        });
+        drop_deep(tree);
+        drop_deep(decoded);
+    }
+}
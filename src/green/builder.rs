@@ -1,6 +1,8 @@
 use crate::{
     cow_mut::CowMut,
-    green::{node_cache::NodeCache, GreenElement, GreenNode, SyntaxKind},
+    green::{
+        node_cache::NodeCache, shared_cache::SharedNodeCache, GreenElement, GreenNode, SyntaxKind,
+    },
     NodeOrToken,
 };
 
@@ -11,11 +13,26 @@ pub struct Checkpoint(usize);
 /// A builder for a green tree.
 #[derive(Default, Debug)]
 pub struct GreenNodeBuilder<'cache> {
-    cache: CowMut<'cache, NodeCache>,
+    cache: Cache<'cache>,
     parents: Vec<(SyntaxKind, usize)>,
     children: Vec<(u64, GreenElement)>,
 }
 
+// The local cache is kept inline, so that a builder costs no allocation
+// until it builds something.
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug)]
+enum Cache<'a> {
+    Local(CowMut<'a, NodeCache>),
+    Shared(&'a SharedNodeCache),
+}
+
+impl Default for Cache<'_> {
+    fn default() -> Self {
+        Cache::Local(CowMut::default())
+    }
+}
+
 impl GreenNodeBuilder<'_> {
     /// Creates new builder.
     pub fn new() -> GreenNodeBuilder<'static> {
@@ -26,7 +43,17 @@ impl GreenNodeBuilder<'_> {
     /// It allows to structurally share underlying trees.
     pub fn with_cache(cache: &mut NodeCache) -> GreenNodeBuilder<'_> {
         GreenNodeBuilder {
-            cache: CowMut::Borrowed(cache),
+            cache: Cache::Local(CowMut::Borrowed(cache)),
+            parents: Vec::new(),
+            children: Vec::new(),
+        }
+    }
+
+    /// Like [`GreenNodeBuilder::with_cache`], but the cache can be shared
+    /// with builders on other threads.
+    pub fn with_shared_cache(cache: &SharedNodeCache) -> GreenNodeBuilder<'_> {
+        GreenNodeBuilder {
+            cache: Cache::Shared(cache),
             parents: Vec::new(),
             children: Vec::new(),
         }
@@ -35,7 +62,10 @@ impl GreenNodeBuilder<'_> {
     /// Adds new token to the current branch.
     #[inline]
     pub fn token(&mut self, kind: SyntaxKind, text: &str) {
-        let (hash, token) = self.cache.token(kind, text);
+        let (hash, token) = match &mut self.cache {
+            Cache::Local(cache) => cache.token(kind, text),
+            Cache::Shared(cache) => cache.token(kind, text),
+        };
         self.children.push((hash, token.into()));
     }
 
@@ -51,7 +81,11 @@ impl GreenNodeBuilder<'_> {
     #[inline]
     pub fn finish_node(&mut self) {
         let (kind, first_child) = self.parents.pop().unwrap();
-        let (hash, node) = self.cache.node(kind, &mut self.children, first_child);
+        let children = &mut self.children;
+        let (hash, node) = match &mut self.cache {
+            Cache::Local(cache) => cache.node(kind, children, first_child),
+            Cache::Shared(cache) => cache.node(kind, children, first_child),
+        };
         self.children.push((hash, node.into()));
     }
 
@@ -111,6 +145,10 @@ impl GreenNodeBuilder<'_> {
     #[inline]
     pub fn finish(mut self) -> GreenNode {
         assert_eq!(self.children.len(), 1);
+        match &mut self.cache {
+            Cache::Local(cache) => cache.enforce_size_limit(),
+            Cache::Shared(_) => {}
+        }
         match self.children.pop().unwrap().1 {
             NodeOrToken::Node(node) => node,
             NodeOrToken::Token(_) => panic!(),
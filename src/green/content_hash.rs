@@ -0,0 +1,83 @@
+//! The structural hash behind [`GreenNodeData::content_hash`].
+//!
+//! [`GreenNodeData::content_hash`]: crate::GreenNodeData::content_hash
+
+use crate::SyntaxKind;
+
+const TOKEN_TAG: u8 = 0;
+const NODE_TAG: u8 = 1;
+
+/// 64-bit FNV-1a. Unlike the hashers of `std` and `rustc-hash` its output is
+/// fixed, so it must never change: values are persisted across sessions.
+pub(super) struct StableHasher(u64);
+
+impl StableHasher {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+
+    fn new() -> StableHasher {
+        StableHasher(StableHasher::OFFSET_BASIS)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= u64::from(byte);
+            self.0 = self.0.wrapping_mul(StableHasher::PRIME);
+        }
+    }
+
+    /// Starts hashing a node of `kind` with `n_children` children, which
+    /// then must be passed to [`StableHasher::child`] in order.
+    pub(super) fn node(kind: SyntaxKind, n_children: usize) -> StableHasher {
+        let mut h = StableHasher::new();
+        h.write(&[NODE_TAG]);
+        h.write(&kind.0.to_le_bytes());
+        h.write(&(n_children as u64).to_le_bytes());
+        h
+    }
+
+    pub(super) fn child(&mut self, content_hash: u64) {
+        self.write(&content_hash.to_le_bytes());
+    }
+
+    pub(super) fn token(kind: SyntaxKind, text: &str) -> u64 {
+        let mut h = StableHasher::new();
+        h.write(&[TOKEN_TAG]);
+        h.write(&kind.0.to_le_bytes());
+        h.write(text.as_bytes());
+        h.finish()
+    }
+
+    pub(super) fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{GreenNode, GreenToken, NodeOrToken, SyntaxKind};
+
+    fn node(texts: &[&str]) -> GreenNode {
+        let tokens = texts
+            .iter()
+            .map(|&it| NodeOrToken::Token(GreenToken::new(SyntaxKind(1), it)));
+        GreenNode::new(SyntaxKind(0), tokens)
+    }
+
+    #[test]
+    fn content_hash() {
+        // Fixed forever, as hashes are compared across sessions.
+        assert_eq!(
+            GreenToken::new(SyntaxKind(1), "a").content_hash(),
+            0x447c_5c7f_98e6_ba1d
+        );
+        assert_eq!(node(&["a", "b"]).content_hash(), 0xea13_8a8b_e962_4f29);
+
+        let hash = node(&["a", "b"]).content_hash();
+        assert_eq!(node(&["a", "b"]).content_hash(), hash);
+        assert_ne!(node(&["ab"]).content_hash(), hash);
+        assert_ne!(node(&["b", "a"]).content_hash(), hash);
+        let nested = GreenNode::new(SyntaxKind(0), [node(&["a", "b"]).into()]);
+        assert_ne!(nested.content_hash(), hash);
+    }
+}
@@ -66,6 +66,12 @@ impl GreenElement {
     pub fn text_len(&self) -> TextSize {
         self.as_deref().text_len()
     }
+
+    /// Returns the stable hash of the contents of this element.
+    #[inline]
+    pub fn content_hash(&self) -> u64 {
+        self.as_deref().content_hash()
+    }
 }
 
 impl GreenElementRef<'_> {
@@ -86,4 +92,13 @@ impl GreenElementRef<'_> {
             NodeOrToken::Token(token) => token.text_len(),
         }
     }
+
+    /// Returns the stable hash of the contents of this element.
+    #[inline]
+    pub fn content_hash(self) -> u64 {
+        match self {
+            NodeOrToken::Node(node) => node.content_hash(),
+            NodeOrToken::Token(token) => token.content_hash(),
+        }
+    }
 }
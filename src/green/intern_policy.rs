@@ -0,0 +1,144 @@
+use rustc_hash::FxHashSet;
+
+use crate::green::SyntaxKind;
+
+/// Decides which nodes and tokens a [`NodeCache`](crate::NodeCache) interns.
+///
+/// The default policy interns all tokens, and nodes with at most three
+/// children. A node is never interned if one of its children isn't, as the
+/// cache relies on children being interned to hash nodes cheaply.
+///
+/// ```
+/// # use rowan_test::{InternPolicy, NodeCache, SyntaxKind};
+/// # const HEX_STRING: SyntaxKind = SyntaxKind(0);
+/// # const COMMENT: SyntaxKind = SyntaxKind(1);
+/// let policy = InternPolicy::new()
+///     .always_intern_node(HEX_STRING)
+///     .never_intern_node(COMMENT)
+///     .max_token_len(32);
+/// let cache = NodeCache::with_policy(policy);
+/// ```
+#[derive(Debug, Clone)]
+pub struct InternPolicy {
+    max_children: usize,
+    always_nodes: FxHashSet<SyntaxKind>,
+    never_nodes: FxHashSet<SyntaxKind>,
+    max_token_len: Option<usize>,
+    always_tokens: FxHashSet<SyntaxKind>,
+    never_tokens: FxHashSet<SyntaxKind>,
+}
+
+impl Default for InternPolicy {
+    fn default() -> InternPolicy {
+        InternPolicy {
+            max_children: 3,
+            always_nodes: FxHashSet::default(),
+            never_nodes: FxHashSet::default(),
+            max_token_len: None,
+            always_tokens: FxHashSet::default(),
+            never_tokens: FxHashSet::default(),
+        }
+    }
+}
+
+impl InternPolicy {
+    pub fn new() -> InternPolicy {
+        InternPolicy::default()
+    }
+
+    /// Nodes with more children are not interned, unless allowed by
+    /// [`InternPolicy::always_intern_node`].
+    pub fn max_children(mut self, max_children: usize) -> InternPolicy {
+        self.max_children = max_children;
+        self
+    }
+
+    /// Interns nodes of `kind` regardless of the number of their children.
+    pub fn always_intern_node(mut self, kind: SyntaxKind) -> InternPolicy {
+        self.never_nodes.remove(&kind);
+        self.always_nodes.insert(kind);
+        self
+    }
+
+    /// Never interns nodes of `kind`, nor their ancestors.
+    pub fn never_intern_node(mut self, kind: SyntaxKind) -> InternPolicy {
+        self.always_nodes.remove(&kind);
+        self.never_nodes.insert(kind);
+        self
+    }
+
+    /// Tokens with longer texts are not interned, unless allowed by
+    /// [`InternPolicy::always_intern_token`].
+    pub fn max_token_len(mut self, max_len: usize) -> InternPolicy {
+        self.max_token_len = Some(max_len);
+        self
+    }
+
+    /// Interns tokens of `kind` regardless of the length of their text.
+    pub fn always_intern_token(mut self, kind: SyntaxKind) -> InternPolicy {
+        self.never_tokens.remove(&kind);
+        self.always_tokens.insert(kind);
+        self
+    }
+
+    /// Never interns tokens of `kind`, nor the nodes containing them.
+    pub fn never_intern_token(mut self, kind: SyntaxKind) -> InternPolicy {
+        self.always_tokens.remove(&kind);
+        self.never_tokens.insert(kind);
+        self
+    }
+
+    pub(crate) fn never_interns_node(&self, kind: SyntaxKind) -> bool {
+        self.never_nodes.contains(&kind)
+    }
+
+    pub(crate) fn allows_children(&self, kind: SyntaxKind, n_children: usize) -> bool {
+        n_children <= self.max_children || self.always_nodes.contains(&kind)
+    }
+
+    pub(crate) fn interns_token(&self, kind: SyntaxKind, text: &str) -> bool {
+        if self.never_tokens.contains(&kind) {
+            return false;
+        }
+        match self.max_token_len {
+            Some(max_len) => text.len() <= max_len || self.always_tokens.contains(&kind),
+            None => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nodes() {
+        let policy = InternPolicy::new().max_children(1);
+        assert!(policy.allows_children(SyntaxKind(0), 1));
+        assert!(!policy.allows_children(SyntaxKind(0), 2));
+
+        let policy = policy.always_intern_node(SyntaxKind(0));
+        assert!(policy.allows_children(SyntaxKind(0), 2));
+        assert!(!policy.never_interns_node(SyntaxKind(0)));
+
+        let policy = policy.never_intern_node(SyntaxKind(0));
+        assert!(!policy.allows_children(SyntaxKind(0), 2));
+        assert!(policy.never_interns_node(SyntaxKind(0)));
+    }
+
+    #[test]
+    fn tokens() {
+        let policy = InternPolicy::new();
+        assert!(policy.interns_token(SyntaxKind(0), "long"));
+
+        let policy = policy.max_token_len(1).always_intern_token(SyntaxKind(1));
+        assert!(policy.interns_token(SyntaxKind(0), "a"));
+        assert!(!policy.interns_token(SyntaxKind(0), "long"));
+        assert!(policy.interns_token(SyntaxKind(1), "long"));
+
+        let policy = policy.never_intern_token(SyntaxKind(1));
+        assert!(!policy.interns_token(SyntaxKind(1), "a"));
+        let policy = policy.always_intern_token(SyntaxKind(1));
+        assert!(policy.interns_token(SyntaxKind(1), "long"));
+    }
+}
@@ -0,0 +1,143 @@
+//! Memory accounting for green trees.
+
+use std::ptr;
+
+use rustc_hash::{FxHashMap, FxHashSet};
+
+use crate::{GreenNodeData, NodeOrToken};
+
+/// Allocation counts of a type, as tracked by `countme`. All zero unless the
+/// `countme` feature is enabled.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AllocCounts {
+    /// Allocations so far.
+    pub total: usize,
+    pub live: usize,
+    /// The largest number of simultaneously live allocations.
+    pub max_live: usize,
+}
+
+impl AllocCounts {
+    pub(crate) fn of<T: 'static>() -> AllocCounts {
+        let counts = countme::get::<T>();
+        AllocCounts {
+            total: counts.total,
+            live: counts.live,
+            max_live: counts.max_live,
+        }
+    }
+}
+
+/// How much memory a green tree takes, see [`GreenNodeData::memory_report`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemoryReport {
+    /// Nodes in the tree, counting a shared node once per occurrence.
+    pub nodes: usize,
+    /// Tokens in the tree, counting a shared token once per occurrence.
+    pub tokens: usize,
+    /// Distinct node allocations in the tree.
+    pub unique_nodes: usize,
+    /// Distinct token allocations in the tree.
+    pub unique_tokens: usize,
+    /// Size of the distinct allocations.
+    pub bytes: usize,
+    /// Size the tree would take if nothing was shared.
+    pub unshared_bytes: usize,
+}
+
+impl GreenNodeData {
+    /// Counts the nodes and tokens of the tree, and how many of them are
+    /// shared with other parts of it.
+    pub fn memory_report(&self) -> MemoryReport {
+        let mut report = MemoryReport::default();
+        let mut seen_tokens = FxHashSet::default();
+        // Per distinct node: nodes, tokens and bytes of the unshared subtree.
+        let mut seen_nodes = FxHashMap::default();
+        report.unique_nodes += 1;
+        report.bytes += self.heap_size();
+        // Nodes whose children are being visited, with the totals so far.
+        let mut stack = vec![(self, self.children(), (1, 0, self.heap_size()))];
+        loop {
+            let (_, children, res) = stack.last_mut().unwrap();
+            match children.next() {
+                Some(NodeOrToken::Node(it)) => match seen_nodes.get(&ptr::addr_of!(*it)) {
+                    Some(&(nodes, tokens, bytes)) => {
+                        *res = (res.0 + nodes, res.1 + tokens, res.2 + bytes);
+                    }
+                    None => {
+                        report.unique_nodes += 1;
+                        report.bytes += it.heap_size();
+                        stack.push((it, it.children(), (1, 0, it.heap_size())));
+                    }
+                },
+                Some(NodeOrToken::Token(it)) => {
+                    if seen_tokens.insert(ptr::addr_of!(*it)) {
+                        report.unique_tokens += 1;
+                        report.bytes += it.heap_size();
+                    }
+                    *res = (res.0, res.1 + 1, res.2 + it.heap_size());
+                }
+                None => {
+                    let (node, _, (nodes, tokens, bytes)) = stack.pop().unwrap();
+                    seen_nodes.insert(ptr::addr_of!(*node), (nodes, tokens, bytes));
+                    match stack.last_mut() {
+                        Some((_, _, res)) => {
+                            *res = (res.0 + nodes, res.1 + tokens, res.2 + bytes);
+                        }
+                        None => {
+                            report.nodes = nodes;
+                            report.tokens = tokens;
+                            report.unshared_bytes = bytes;
+                            return report;
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{test_utils::drop_deep, GreenNode, GreenToken, SyntaxKind};
+
+    fn leaf(text: &str) -> GreenNode {
+        let token = GreenToken::new(SyntaxKind(1), text);
+        GreenNode::new(SyntaxKind(2), vec![token.into()])
+    }
+
+    #[test]
+    fn counts_shared_subtrees_once() {
+        let shared = leaf("a");
+        let tree = GreenNode::new(
+            SyntaxKind(0),
+            vec![
+                shared.clone().into(),
+                shared.clone().into(),
+                leaf("b").into(),
+            ],
+        );
+        let report = tree.memory_report();
+        assert_eq!((report.nodes, report.unique_nodes), (4, 3));
+        assert_eq!((report.tokens, report.unique_tokens), (3, 2));
+        let token = GreenToken::new(SyntaxKind(1), "a");
+        assert_eq!(
+            report.unshared_bytes,
+            report.bytes + shared.heap_size() + token.heap_size()
+        );
+    }
+
+    #[test]
+    fn deep_tree() {
+        let depth = 100_000;
+        let mut tree = leaf("a");
+        for _ in 0..depth {
+            tree = GreenNode::new(SyntaxKind(0), vec![tree.into()]);
+        }
+        let report = tree.memory_report();
+        assert_eq!((report.nodes, report.unique_nodes), (depth + 1, depth + 1));
+        assert_eq!((report.tokens, report.unique_tokens), (1, 1));
+        assert_eq!(report.bytes, report.unshared_bytes);
+        drop_deep(tree);
+    }
+}
@@ -9,8 +9,8 @@ use std::{
 use countme::Count;
 
 use crate::{
-    arc::{Arc, HeaderSlice, ThinArc},
-    green::{GreenElement, GreenElementRef, SyntaxKind},
+    arc::{Arc, ArcInner, HeaderSlice, ThinArc},
+    green::{content_hash::StableHasher, GreenElement, GreenElementRef, SyntaxKind},
     utility_types::static_assert,
     GreenToken, NodeOrToken, TextRange, TextSize,
 };
@@ -19,6 +19,7 @@ use crate::{
 pub(super) struct GreenNodeHead {
     kind: SyntaxKind,
     text_len: TextSize,
+    content_hash: u64,
     _c: Count<GreenNode>,
 }
 
@@ -128,6 +129,11 @@ impl GreenNodeData {
         self.data.slice()
     }
 
+    /// Size of the allocation of this node, not counting its children.
+    pub(crate) fn heap_size(&self) -> usize {
+        mem::size_of::<ArcInner<ReprThin>>() + mem::size_of_val(self.slice())
+    }
+
     /// Kind of this node
     #[inline]
     pub fn kind(&self) -> SyntaxKind {
@@ -140,6 +146,17 @@ impl GreenNodeData {
         self.header().text_len
     }
 
+    /// A hash of the kinds and texts of this node and all of its
+    /// descendants, computed when the node is created.
+    ///
+    /// Unlike the hashes used for interning, it is the same in every process
+    /// and on every platform, so it can be persisted and used to check
+    /// whether a subtree changed between sessions.
+    #[inline]
+    pub fn content_hash(&self) -> u64 {
+        self.header().content_hash
+    }
+
     /// Children of this node
     #[inline]
     pub fn children(&self) -> Children<'_> {
@@ -167,6 +184,21 @@ impl GreenNodeData {
         Some((idx, child.rel_offset(), child.as_ref()))
     }
 
+    /// Children whose range contains `rel_offset`, including at its ends, in
+    /// order, found by binary search.
+    pub(crate) fn children_at_offset(
+        &self,
+        rel_offset: TextSize,
+    ) -> impl Iterator<Item = (usize, &GreenChild)> {
+        let slice = self.slice();
+        let start = slice.partition_point(|it| it.rel_range().end() < rel_offset);
+        slice[start..]
+            .iter()
+            .enumerate()
+            .map(move |(i, it)| (start + i, it))
+            .take_while(move |(_, it)| it.rel_offset() <= rel_offset)
+    }
+
     #[must_use]
     pub fn replace_child(&self, index: usize, new_child: GreenElement) -> GreenNode {
         let mut replacement = Some(new_child);
@@ -180,9 +212,11 @@ impl GreenNodeData {
         GreenNode::new(self.kind(), children)
     }
 
+    /// Returns a copy of this node with `new_child` inserted before the child
+    /// at `index`, or appended if `index` is the number of children.
     #[must_use]
     pub fn insert_child(&self, index: usize, new_child: GreenElement) -> GreenNode {
-        self.splice_children(index..=index, iter::once(new_child))
+        self.splice_children(index..index, iter::once(new_child))
     }
 
     #[must_use]
@@ -223,10 +257,13 @@ impl GreenNode {
         I: IntoIterator<Item = GreenElement>,
         I::IntoIter: ExactSizeIterator,
     {
+        let children = children.into_iter();
         let mut text_len: TextSize = 0.into();
-        let children = children.into_iter().map(|el| {
+        let mut hasher = StableHasher::node(kind, children.len());
+        let children = children.map(|el| {
             let rel_offset = text_len;
             text_len += el.text_len();
+            hasher.child(el.content_hash());
             match el {
                 NodeOrToken::Node(it) => GreenChild::Node {
                     rel_offset,
@@ -243,21 +280,31 @@ impl GreenNode {
             GreenNodeHead {
                 kind,
                 text_len: 0.into(),
+                content_hash: 0,
                 _c: Count::new(),
             },
             children,
         );
 
-        // Fix text_len after it is constructed because we cannot iterate children twice
+        // Fix text_len and content_hash after it is constructed because we
+        // cannot iterate children twice
         let data = {
             let mut data = Arc::from_thin(data);
-            Arc::get_mut(&mut data).unwrap().header.text_len = text_len;
+            let header = &mut Arc::get_mut(&mut data).unwrap().header;
+            header.text_len = text_len;
+            header.content_hash = hasher.finish();
             Arc::into_thin(data)
         };
 
         GreenNode { ptr: data }
     }
 
+    /// Whether this is the only reference to the node.
+    #[inline]
+    pub(crate) fn is_unique(&self) -> bool {
+        self.ptr.with_arc(|it| it.is_unique())
+    }
+
     #[inline]
     pub(crate) fn into_raw(this: GreenNode) -> ptr::NonNull<GreenNodeData> {
         let green = ManuallyDrop::new(this);
@@ -381,3 +428,26 @@ impl<'a> DoubleEndedIterator for Children<'a> {
 }
 
 impl FusedIterator for Children<'_> {}
+
+#[cfg(test)]
+mod tests {
+    use crate::{GreenToken, NodeOrToken};
+
+    use super::*;
+
+    fn node(texts: &[&str]) -> GreenNode {
+        let tokens = texts
+            .iter()
+            .map(|&it| NodeOrToken::Token(GreenToken::new(SyntaxKind(1), it)));
+        GreenNode::new(SyntaxKind(0), tokens)
+    }
+
+    #[test]
+    fn insert_child_keeps_existing_children() {
+        let old = node(&["a", "b"]);
+        let new_child = || GreenToken::new(SyntaxKind(1), "x").into();
+        assert_eq!(old.insert_child(0, new_child()), node(&["x", "a", "b"]));
+        assert_eq!(old.insert_child(1, new_child()), node(&["a", "x", "b"]));
+        assert_eq!(old.insert_child(2, new_child()), node(&["a", "b", "x"]));
+    }
+}
@@ -0,0 +1,204 @@
+//! Relexing of a single token, the fast path of incremental reparsing for
+//! edits which don't leave the token they start in.
+
+use std::{error::Error, fmt};
+
+use crate::{
+    green::{GreenToken, GreenTokenData, SyntaxKind},
+    text_edit::Indel,
+    TextRange,
+};
+
+/// The reason why an edit can't be handled by relexing a single token. The
+/// caller should fall back to reparsing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RelexError {
+    /// The edit is not contained in the token.
+    EditOutsideToken,
+    /// The edited text is not a single token anymore.
+    NotSingleToken,
+    /// The edited text is a single token, but of another kind.
+    KindChanged,
+    /// The edited token would be glued together with the following token.
+    MergesWithNext,
+    /// The edited token would be glued together with the preceding token.
+    MergesWithPrevious,
+}
+
+impl fmt::Display for RelexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let msg = match self {
+            RelexError::EditOutsideToken => "edit is not contained in the token",
+            RelexError::NotSingleToken => "edited text is not a single token",
+            RelexError::KindChanged => "edited token changed its kind",
+            RelexError::MergesWithNext => "edited token merges with the next token",
+            RelexError::MergesWithPrevious => "edited token merges with the previous token",
+        };
+        f.write_str(msg)
+    }
+}
+
+impl Error for RelexError {}
+
+impl GreenTokenData {
+    /// Returns a copy of this token with `edit` applied. The offsets of
+    /// `edit` are relative to the start of the token.
+    ///
+    /// `relex` should return the kind of the token if the whole text is
+    /// exactly one token. The kind must be the same as the kind of `self`.
+    /// [`Lexer::lex_single`](crate::lexer::Lexer::lex_single) is such a
+    /// function.
+    pub fn relex<F>(&self, edit: &Indel, mut relex: F) -> Result<GreenToken, RelexError>
+    where
+        F: FnMut(&str) -> Option<SyntaxKind>,
+    {
+        if !TextRange::up_to(self.text_len()).contains_range(edit.delete) {
+            return Err(RelexError::EditOutsideToken);
+        }
+        let mut text = self.text().to_string();
+        edit.apply(&mut text);
+        match relex(&text) {
+            None => Err(RelexError::NotSingleToken),
+            Some(kind) if kind != self.kind() => Err(RelexError::KindChanged),
+            Some(kind) => Ok(GreenToken::new(kind, &text)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        api, red, test_utils::TestLang, GreenNode, GreenNodeBuilder, NodeOrToken, SyntaxKind,
+        TextRange,
+    };
+
+    use super::*;
+
+    const ROOT: SyntaxKind = SyntaxKind(0);
+    const WORD: SyntaxKind = SyntaxKind(1);
+    const INT: SyntaxKind = SyntaxKind(2);
+    const OP: SyntaxKind = SyntaxKind(3);
+
+    fn lex_single(text: &str) -> Option<SyntaxKind> {
+        if text.is_empty() {
+            None
+        } else if text.bytes().all(|it| it.is_ascii_lowercase()) {
+            Some(WORD)
+        } else if text.bytes().all(|it| it.is_ascii_digit()) {
+            Some(INT)
+        } else if ["<", "<=", "-", "=", "=="].contains(&text) {
+            Some(OP)
+        } else {
+            None
+        }
+    }
+
+    fn tree(tokens: &[(SyntaxKind, &str)]) -> GreenNode {
+        let mut builder = GreenNodeBuilder::new();
+        builder.start_node(ROOT);
+        for &(kind, text) in tokens {
+            builder.token(kind, text);
+        }
+        builder.finish_node();
+        builder.finish()
+    }
+
+    #[test]
+    fn relex_green_token() {
+        let token = GreenToken::new(WORD, "ab");
+        let relexed = token.relex(&Indel::insert(1.into(), "x".to_string()), lex_single);
+        assert_eq!(relexed, Ok(GreenToken::new(WORD, "axb")));
+
+        let outside = Indel::delete(TextRange::new(1.into(), 3.into()));
+        assert_eq!(
+            token.relex(&outside, lex_single),
+            Err(RelexError::EditOutsideToken)
+        );
+        let split = Indel::insert(1.into(), "<".to_string());
+        assert_eq!(
+            token.relex(&split, lex_single),
+            Err(RelexError::NotSingleToken)
+        );
+        let to_int = Indel::replace(TextRange::up_to(2.into()), "12".to_string());
+        assert_eq!(
+            token.relex(&to_int, lex_single),
+            Err(RelexError::KindChanged)
+        );
+    }
+
+    #[test]
+    fn relex_red_token() {
+        // a<-b
+        let green = tree(&[(WORD, "a"), (OP, "<"), (OP, "-"), (WORD, "b")]);
+        let root = red::SyntaxNode::new_root(green.clone());
+        let token = |idx: usize| {
+            root.children_with_tokens()
+                .nth(idx)
+                .unwrap()
+                .into_token()
+                .unwrap()
+        };
+
+        let new_root = token(3)
+            .relex(&Indel::insert(4.into(), "c".to_string()), lex_single)
+            .unwrap();
+        assert_eq!(new_root.to_string(), "a<-bc");
+        let first = |node: &GreenNode| {
+            node.children()
+                .next()
+                .and_then(NodeOrToken::into_token)
+                .unwrap() as *const _
+        };
+        assert!(std::ptr::eq(first(&green), first(&new_root)));
+
+        // Offsets are absolute, so this edit is in `a`, not in `<`.
+        let edit = Indel::insert(0.into(), "x".to_string());
+        assert_eq!(
+            token(1).relex(&edit, lex_single),
+            Err(RelexError::EditOutsideToken)
+        );
+
+        // `-` => `=` turns `<-` into `<=`.
+        let edit = Indel::replace(TextRange::new(2.into(), 3.into()), "=".to_string());
+        assert_eq!(
+            token(2).relex(&edit, lex_single),
+            Err(RelexError::MergesWithPrevious)
+        );
+        // `<` => `=` turns `<-` into `=-`, which is fine.
+        let edit = Indel::replace(TextRange::new(1.into(), 2.into()), "=".to_string());
+        assert_eq!(
+            token(1).relex(&edit, lex_single).unwrap().to_string(),
+            "a=-b"
+        );
+
+        // a-=b, with an empty token in between.
+        let green = tree(&[(WORD, "a"), (OP, "-"), (OP, ""), (OP, "="), (WORD, "b")]);
+        let root = red::SyntaxNode::new_root(green);
+        let minus = root
+            .children_with_tokens()
+            .nth(1)
+            .unwrap()
+            .into_token()
+            .unwrap();
+        let edit = Indel::replace(TextRange::new(1.into(), 2.into()), "=".to_string());
+        assert_eq!(
+            minus.relex(&edit, lex_single),
+            Err(RelexError::MergesWithNext)
+        );
+    }
+
+    #[test]
+    fn relex_api_token() {
+        let green = tree(&[(WORD, "ab"), (OP, "<"), (INT, "1")]);
+        let root = api::SyntaxNode::<TestLang>::new_root(green);
+        let int = root.last_token().unwrap();
+        let new_root = int
+            .relex(&Indel::insert(4.into(), "2".to_string()), lex_single)
+            .unwrap();
+        assert_eq!(new_root.to_string(), "ab<12");
+        assert_eq!(
+            int.relex(&Indel::insert(3.into(), "x".to_string()), lex_single),
+            Err(RelexError::NotSingleToken)
+        );
+    }
+}
@@ -0,0 +1,187 @@
+//! Incremental reparsing of green trees.
+//!
+//! Instead of parsing the whole file again after an edit, we look for the
+//! smallest node around the edit which the parser knows how to parse in
+//! isolation (a block, a rule body, ...), reparse just its text and splice the
+//! result back. Everything outside of that node is reused as is.
+
+use crate::{
+    green::{GreenNode, GreenNodeData, SyntaxKind},
+    text_edit::{Indel, TextEdit},
+    NodeOrToken, TextRange, TextSize,
+};
+
+impl GreenNodeData {
+    /// Returns the tree with `edit` applied, reparsing as little as possible.
+    ///
+    /// `reparse` is called with the kind and the edited text of a candidate
+    /// node, starting from the innermost node containing all of the edit, and
+    /// should return the new node if that kind can be parsed on its own. If it
+    /// returns `None`, or a node of a different kind, the next enclosing node
+    /// is tried. Returns `None` if not even `self` could be reparsed, or if
+    /// `reparse` returned a node whose text length differs from the edited
+    /// text. An empty edit returns `self` unchanged.
+    ///
+    /// Apart from the root, only nodes which contain the edit strictly inside
+    /// are considered: an edit touching the boundary of a node might as well
+    /// change its neighbours.
+    pub fn reparse<F>(&self, edit: &TextEdit, mut reparse: F) -> Option<GreenNode>
+    where
+        F: FnMut(SyntaxKind, &str) -> Option<GreenNode>,
+    {
+        let edit_range = match (edit.iter().next(), edit.iter().last()) {
+            (Some(first), Some(last)) => first.delete.cover(last.delete),
+            _ => return Some(self.to_owned()),
+        };
+        assert!(
+            TextRange::up_to(self.text_len()).contains_range(edit_range),
+            "Bad edit: node range {:?}, edit {:?}",
+            TextRange::up_to(self.text_len()),
+            edit_range,
+        );
+
+        // Path from the root to the innermost candidate: the node, its index
+        // in the parent, and its absolute offset.
+        let mut path: Vec<(&GreenNodeData, usize, TextSize)> = vec![(self, 0, 0.into())];
+        loop {
+            let &(node, _, offset) = path.last().unwrap();
+            let rel_range = edit_range - offset;
+            let child = node
+                .child_at_range(rel_range)
+                .and_then(|(index, rel_offset, child)| match child {
+                    NodeOrToken::Node(child) => Some((child, index, offset + rel_offset)),
+                    NodeOrToken::Token(_) => None,
+                })
+                .filter(|&(child, _, offset)| {
+                    let range = TextRange::at(offset, child.text_len());
+                    range.start() < edit_range.start() && edit_range.end() < range.end()
+                });
+            match child {
+                Some(it) => path.push(it),
+                None => break,
+            }
+        }
+
+        while let Some((node, mut index, offset)) = path.pop() {
+            let mut text = node.to_string();
+            // Going backwards keeps the offsets of the remaining indels valid.
+            for indel in edit.iter().rev() {
+                Indel::replace(indel.delete - offset, indel.insert.clone()).apply(&mut text);
+            }
+            let mut new_node = match reparse(node.kind(), &text) {
+                Some(it) if it.kind() == node.kind() => it,
+                _ => continue,
+            };
+            if new_node.text_len() != TextSize::of(&text) {
+                return None;
+            }
+
+            while let Some((parent, parent_index, _)) = path.pop() {
+                new_node = parent.replace_child(index, new_node.into());
+                index = parent_index;
+            }
+            return Some(new_node);
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{GreenNodeBuilder, GreenNodeData};
+
+    use super::*;
+
+    const ROOT: SyntaxKind = SyntaxKind(0);
+    const BLOCK: SyntaxKind = SyntaxKind(1);
+    const WORD: SyntaxKind = SyntaxKind(2);
+    const PUNCT: SyntaxKind = SyntaxKind(3);
+
+    /// `{a b}{c}` => ROOT(BLOCK({ a b }) BLOCK({ c }))
+    fn parse_block(builder: &mut GreenNodeBuilder, text: &str) {
+        builder.start_node(BLOCK);
+        for part in text.split_inclusive(['{', '}', ' ']) {
+            let (word, punct) = part.split_at(part.len() - 1);
+            if !word.is_empty() {
+                builder.token(WORD, word);
+            }
+            builder.token(PUNCT, punct);
+        }
+        builder.finish_node();
+    }
+
+    fn parse(text: &str) -> GreenNode {
+        let mut builder = GreenNodeBuilder::new();
+        builder.start_node(ROOT);
+        for block in text.split_inclusive('}') {
+            parse_block(&mut builder, block);
+        }
+        builder.finish_node();
+        builder.finish()
+    }
+
+    fn reparse_block(kind: SyntaxKind, text: &str) -> Option<GreenNode> {
+        if kind != BLOCK || !text.starts_with('{') || text[1..].find('}') != Some(text.len() - 2) {
+            return None;
+        }
+        let mut builder = GreenNodeBuilder::new();
+        parse_block(&mut builder, text);
+        Some(builder.finish())
+    }
+
+    #[test]
+    fn reparse_reuses_untouched_nodes() {
+        let old = parse("{a b}{c}");
+        let edit = Indel::replace(TextRange::new(1.into(), 2.into()), "xy".to_string());
+        let new = old.reparse(&edit.into(), reparse_block).unwrap();
+        assert_eq!(new.to_string(), "{xy b}{c}");
+        assert_eq!(new, parse("{xy b}{c}"));
+
+        let child = |node: &GreenNode, idx| -> *const GreenNodeData {
+            node.children().nth(idx).unwrap().into_node().unwrap()
+        };
+        assert!(std::ptr::eq(child(&old, 1), child(&new, 1)));
+
+        // Unbalanced edit inside the block falls back to the root.
+        let edit = Indel::insert(2.into(), "}".to_string());
+        assert_eq!(old.reparse(&edit.into(), reparse_block), None);
+    }
+
+    #[test]
+    fn reparse_several_indels() {
+        let old = parse("{a b c}{d}");
+        let edit = TextEdit::new(vec![
+            Indel::insert(5.into(), "x".to_string()),
+            Indel::replace(TextRange::new(1.into(), 2.into()), "yz".to_string()),
+        ]);
+        let new = old.reparse(&edit, reparse_block).unwrap();
+        assert_eq!(new, parse("{yz b xc}{d}"));
+
+        // Both indels must fit in a single block.
+        let edit = TextEdit::new(vec![
+            Indel::delete(TextRange::new(1.into(), 2.into())),
+            Indel::delete(TextRange::new(8.into(), 9.into())),
+        ]);
+        assert_eq!(old.reparse(&edit, reparse_block), None);
+
+        assert_eq!(
+            old.reparse(&TextEdit::default(), reparse_block),
+            Some(old.clone())
+        );
+    }
+
+    #[test]
+    fn reparse_rejects_wrong_length() {
+        let old = parse("{a b}{c}");
+        let edit = Indel::insert(2.into(), "x".to_string()).into();
+        let res = old.reparse(&edit, |kind, _| {
+            if kind != BLOCK {
+                return None;
+            }
+            let mut builder = GreenNodeBuilder::new();
+            parse_block(&mut builder, "{a}");
+            Some(builder.finish())
+        });
+        assert_eq!(res, None);
+    }
+}
@@ -0,0 +1,318 @@
+use std::{
+    ptr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex, MutexGuard,
+    },
+};
+
+use hashbrown::hash_map::RawEntryMut;
+use rustc_hash::FxHashMap;
+
+use crate::{GreenNode, GreenNodeData, GreenToken, NodeCacheStats, SyntaxKind};
+
+use super::{
+    element::GreenElement,
+    intern_policy::InternPolicy,
+    node_cache::{self, Counters, NodeMap, Skip, TokenMap},
+};
+
+const DEFAULT_SHARDS: usize = 16;
+
+/// An interner like [`NodeCache`](crate::NodeCache) which can be used by
+/// many [`GreenNodeBuilder`]s at once, from any number of threads.
+///
+/// Nodes and tokens are spread over independently locked shards by their
+/// hash, which is the same as in a `NodeCache`, so that equal subtrees built
+/// on different threads end up as the same allocation.
+///
+/// The cache only shrinks through [`SharedNodeCache::collect_garbage`].
+///
+/// ```
+/// # use rowan_test::{GreenNodeBuilder, SharedNodeCache, SyntaxKind};
+/// let cache = SharedNodeCache::new();
+/// let trees = std::thread::scope(|s| {
+///     let handles = [(); 2].map(|()| {
+///         s.spawn(|| {
+///             let mut builder = GreenNodeBuilder::with_shared_cache(&cache);
+///             builder.start_node(SyntaxKind(0));
+///             builder.token(SyntaxKind(1), "rule");
+///             builder.finish_node();
+///             builder.finish()
+///         })
+///     });
+///     handles.map(|it| it.join().unwrap())
+/// });
+/// assert!(std::ptr::eq(&*trees[0], &*trees[1]));
+/// ```
+///
+/// [`GreenNodeBuilder`]: crate::GreenNodeBuilder
+#[derive(Debug)]
+pub struct SharedNodeCache {
+    shards: Box<[Mutex<Shard>]>,
+    policy: InternPolicy,
+    /// Skipped nodes and tokens, indexed by `Skip`. Hits and misses are
+    /// counted by the shards.
+    skips: [AtomicU64; 3],
+}
+
+#[derive(Default, Debug)]
+struct Shard {
+    nodes: NodeMap,
+    tokens: TokenMap,
+    counters: Counters,
+}
+
+impl Default for SharedNodeCache {
+    fn default() -> SharedNodeCache {
+        SharedNodeCache::with_shards(DEFAULT_SHARDS)
+    }
+}
+
+impl SharedNodeCache {
+    pub fn new() -> SharedNodeCache {
+        SharedNodeCache::default()
+    }
+
+    /// Creates a cache with `n_shards` shards, more shards mean less
+    /// contention between threads. Panics if `n_shards` is zero.
+    pub fn with_shards(n_shards: usize) -> SharedNodeCache {
+        assert!(n_shards > 0, "a SharedNodeCache needs at least one shard");
+        SharedNodeCache {
+            shards: (0..n_shards).map(|_| Mutex::default()).collect(),
+            policy: InternPolicy::default(),
+            skips: Default::default(),
+        }
+    }
+
+    /// Creates a cache which interns according to `policy`.
+    pub fn with_policy(policy: InternPolicy) -> SharedNodeCache {
+        SharedNodeCache {
+            policy,
+            ..SharedNodeCache::default()
+        }
+    }
+
+    pub fn policy(&self) -> &InternPolicy {
+        &self.policy
+    }
+
+    /// Number of cached nodes and tokens.
+    pub fn len(&self) -> usize {
+        (0..self.shards.len())
+            .map(|i| {
+                let shard = self.lock(i);
+                shard.nodes.len() + shard.tokens.len()
+            })
+            .sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Like [`NodeCache::stats`](crate::NodeCache::stats), summed over all
+    /// shards.
+    pub fn stats(&self) -> NodeCacheStats {
+        let skips = |skip: Skip| self.skips[skip as usize].load(Ordering::Relaxed);
+        let mut counters = Counters {
+            skipped_by_policy: skips(Skip::Policy),
+            skipped_many_children: skips(Skip::ManyChildren),
+            skipped_unhashed_children: skips(Skip::UnhashedChild),
+            ..Counters::default()
+        };
+        let (mut nodes, mut tokens, mut bytes) = (0, 0, 0);
+        for i in 0..self.shards.len() {
+            let shard = self.lock(i);
+            nodes += shard.nodes.len();
+            tokens += shard.tokens.len();
+            bytes += node_cache::maps_size(&shard.nodes, &shard.tokens);
+            counters.add(&shard.counters);
+        }
+        NodeCacheStats::new(nodes, tokens, bytes, counters)
+    }
+
+    /// Resets the hit, miss and skip counters.
+    pub fn reset_stats(&self) {
+        for skip in &self.skips {
+            skip.store(0, Ordering::Relaxed);
+        }
+        for i in 0..self.shards.len() {
+            self.lock(i).counters = Counters::default();
+        }
+    }
+
+    /// Drops the nodes and tokens which are referenced by nothing but the
+    /// cache itself, returning how many were dropped.
+    ///
+    /// Trees being built concurrently hold on to their nodes, so they are
+    /// never affected.
+    pub fn collect_garbage(&self) -> usize {
+        let mut dropped = 0;
+        let mut hashes = FxHashMap::default();
+        let mut dead = Vec::new();
+        for i in 0..self.shards.len() {
+            let mut shard = self.lock(i);
+            hashes.extend(shard.nodes.keys().map(|it| (ptr::addr_of!(*it.0), it.1)));
+            let len = shard.nodes.len();
+            shard.nodes.retain(|node, ()| {
+                let unique = node.0.is_unique();
+                if unique {
+                    dead.push(node.0.clone());
+                }
+                !unique
+            });
+            dropped += len - shard.nodes.len();
+        }
+        // Dropping a node may leave its children, which may live in other
+        // shards, unreferenced. Nodes interned since the start are left for
+        // the next collection.
+        while let Some(node) = dead.pop() {
+            let children: Vec<*const GreenNodeData> = node
+                .children()
+                .filter_map(|it| Some(ptr::addr_of!(*it.into_node()?)))
+                .collect();
+            drop(node);
+            for child in children {
+                let hash = match hashes.get(&child) {
+                    Some(&it) => it,
+                    None => continue,
+                };
+                let mut shard = self.shard(hash);
+                let entry = shard
+                    .nodes
+                    .raw_entry_mut()
+                    .from_hash(hash, |it| ptr::eq(&*it.0, child));
+                if let RawEntryMut::Occupied(entry) = entry {
+                    if entry.key().0.is_unique() {
+                        dead.push(entry.remove_entry().0 .0);
+                        dropped += 1;
+                    }
+                }
+            }
+        }
+        for i in 0..self.shards.len() {
+            let mut shard = self.lock(i);
+            let len = shard.tokens.len();
+            shard.tokens.retain(|token, ()| !token.0.is_unique());
+            dropped += len - shard.tokens.len();
+        }
+        dropped
+    }
+
+    pub(crate) fn node(
+        &self,
+        kind: SyntaxKind,
+        children: &mut Vec<(u64, GreenElement)>,
+        first_child: usize,
+    ) -> (u64, GreenNode) {
+        let hash = match node_cache::node_key(&self.policy, kind, &children[first_child..]) {
+            Ok(it) => it,
+            Err(skip) => {
+                self.record_skip(skip);
+                return (0, node_cache::build_node(kind, children, first_child));
+            }
+        };
+        let mut shard = self.shard(hash);
+        let (node, hit) =
+            node_cache::intern_node(&mut shard.nodes, hash, kind, children, first_child);
+        if hit {
+            shard.counters.node_hits += 1;
+        } else {
+            shard.counters.node_misses += 1;
+        }
+        (hash, node)
+    }
+
+    pub(crate) fn token(&self, kind: SyntaxKind, text: &str) -> (u64, GreenToken) {
+        let hash = match node_cache::token_key(&self.policy, kind, text) {
+            Some(it) => it,
+            None => {
+                self.record_skip(Skip::Policy);
+                return (0, GreenToken::new(kind, text));
+            }
+        };
+        let mut shard = self.shard(hash);
+        let (token, hit) = node_cache::intern_token(&mut shard.tokens, hash, kind, text);
+        if hit {
+            shard.counters.token_hits += 1;
+        } else {
+            shard.counters.token_misses += 1;
+        }
+        (hash, token)
+    }
+
+    fn record_skip(&self, skip: Skip) {
+        self.skips[skip as usize].fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn shard(&self, hash: u64) -> MutexGuard<'_, Shard> {
+        // hashbrown picks buckets by the low bits and tags entries with the
+        // top seven, so shards are picked by the bits in between.
+        self.lock((hash >> 32) as usize % self.shards.len())
+    }
+
+    fn lock(&self, i: usize) -> MutexGuard<'_, Shard> {
+        // The maps are never left in an inconsistent state by a panic, so a
+        // poisoned shard is fine to use.
+        self.shards[i]
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ptr, thread};
+
+    use crate::{GreenNodeBuilder, GreenNodeData};
+
+    use super::*;
+
+    fn build(cache: &SharedNodeCache, words: &[&str]) -> GreenNode {
+        let mut builder = GreenNodeBuilder::with_shared_cache(cache);
+        builder.start_node(SyntaxKind(0));
+        for word in words {
+            builder.start_node(SyntaxKind(1));
+            builder.token(SyntaxKind(2), word);
+            builder.finish_node();
+        }
+        builder.finish_node();
+        builder.finish()
+    }
+
+    #[test]
+    fn shared_between_threads() {
+        let cache = SharedNodeCache::with_shards(4);
+        let words = ["a", "b", "c"];
+        let trees = thread::scope(|s| {
+            let handles: Vec<_> = (0..8).map(|_| s.spawn(|| build(&cache, &words))).collect();
+            handles
+                .into_iter()
+                .map(|it| it.join().unwrap())
+                .collect::<Vec<_>>()
+        });
+        for tree in &trees {
+            assert!(ptr::eq::<GreenNodeData>(&*trees[0], &**tree));
+        }
+        assert_eq!(cache.len(), 1 + 3 + 3);
+        let stats = cache.stats();
+        assert_eq!((stats.nodes, stats.tokens), (4, 3));
+        assert_eq!(stats.node_hits + stats.node_misses, 8 * 4);
+        assert_eq!((stats.token_misses, stats.skipped_by_policy), (3, 0));
+
+        let child_hash = node_cache::node_hash(&trees[0]);
+        let mut children = vec![(child_hash, trees[0].to_owned().into())];
+        let (hash, node) = cache.node(SyntaxKind(3), &mut children, 0);
+        assert_eq!(hash, node_cache::node_hash(&node));
+
+        drop((trees, node));
+        drop(build(&cache, &["a", "b", "c", "d"]));
+        assert_eq!(cache.stats().skipped_many_children, 1);
+        cache.reset_stats();
+        assert_eq!(cache.stats().node_hits, 0);
+
+        assert_eq!(cache.collect_garbage(), 10);
+        assert!(cache.is_empty());
+    }
+}
@@ -8,14 +8,15 @@ use std::{
 use countme::Count;
 
 use crate::{
-    arc::{Arc, HeaderSlice, ThinArc},
-    green::SyntaxKind,
+    arc::{Arc, ArcInner, HeaderSlice, ThinArc},
+    green::{content_hash::StableHasher, SyntaxKind},
     TextSize,
 };
 
 #[derive(PartialEq, Eq, Hash)]
 struct GreenTokenHead {
     kind: SyntaxKind,
+    content_hash: u64,
     _c: Count<GreenToken>,
 }
 
@@ -88,6 +89,11 @@ impl fmt::Display for GreenTokenData {
 }
 
 impl GreenTokenData {
+    /// Size of the allocation of this token.
+    pub(crate) fn heap_size(&self) -> usize {
+        mem::size_of::<ArcInner<ReprThin>>() + self.text().len()
+    }
+
     /// Kind of this token
     #[inline]
     pub fn kind(&self) -> SyntaxKind {
@@ -100,6 +106,13 @@ impl GreenTokenData {
         unsafe { std::str::from_utf8_unchecked(self.data.slice()) }
     }
 
+    /// A hash of the kind and text of this token, see
+    /// [`GreenNodeData::content_hash`](crate::GreenNodeData::content_hash).
+    #[inline]
+    pub fn content_hash(&self) -> u64 {
+        self.data.header.content_hash
+    }
+
     /// Length of the text that is covered by this token
     pub fn text_len(&self) -> TextSize {
         TextSize::of(self.text())
@@ -112,6 +125,7 @@ impl GreenToken {
     pub fn new(kind: SyntaxKind, text: &str) -> GreenToken {
         let head = GreenTokenHead {
             kind,
+            content_hash: StableHasher::token(kind, text),
             _c: Count::new(),
         };
         // this is just to alloc(usize(for rc) + size_of(head) + text.len())
@@ -119,6 +133,12 @@ impl GreenToken {
         GreenToken { ptr }
     }
 
+    /// Whether this is the only reference to the token.
+    #[inline]
+    pub(crate) fn is_unique(&self) -> bool {
+        self.ptr.with_arc(|it| it.is_unique())
+    }
+
     #[inline]
     pub(crate) fn into_raw(this: GreenToken) -> ptr::NonNull<GreenTokenData> {
         let green: &GreenTokenData = &*ManuallyDrop::new(this);
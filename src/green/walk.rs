@@ -0,0 +1,395 @@
+//! Traversal and offset lookup directly on green trees.
+//!
+//! Unlike their counterparts in [`red`](crate::red), these don't allocate
+//! per visited element. Offsets are relative to the start of the green node
+//! the walk or lookup began at. The results can be turned into red cursors
+//! with the `to_red` methods of [`GreenAt`].
+
+use std::{ptr, slice};
+
+use crate::{
+    green::{GreenChild, GreenElementRef},
+    red, GreenNodeData, GreenTokenData, NodeOrToken, TextRange, TextSize, TokenAtOffset, WalkEvent,
+};
+
+/// A part of a green tree, together with its offset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GreenAt<T> {
+    pub green: T,
+    pub offset: TextSize,
+}
+
+impl<'a> GreenAt<&'a GreenNodeData> {
+    pub fn text_range(&self) -> TextRange {
+        TextRange::at(self.offset, self.green.text_len())
+    }
+
+    /// Finds this node below `root`, which must be the red node for the
+    /// green node this one was found from.
+    pub fn to_red(&self, root: &red::SyntaxNode) -> Option<red::SyntaxNode> {
+        self.element().to_red(root)?.into_node()
+    }
+
+    fn element(&self) -> GreenAt<GreenElementRef<'a>> {
+        GreenAt {
+            green: NodeOrToken::Node(self.green),
+            offset: self.offset,
+        }
+    }
+}
+
+impl<'a> GreenAt<&'a GreenTokenData> {
+    pub fn text_range(&self) -> TextRange {
+        TextRange::at(self.offset, self.green.text_len())
+    }
+
+    /// Finds this token below `root`, which must be the red node for the
+    /// green node this one was found from.
+    pub fn to_red(&self, root: &red::SyntaxNode) -> Option<red::SyntaxToken> {
+        self.element().to_red(root)?.into_token()
+    }
+
+    fn element(&self) -> GreenAt<GreenElementRef<'a>> {
+        GreenAt {
+            green: NodeOrToken::Token(self.green),
+            offset: self.offset,
+        }
+    }
+}
+
+impl GreenAt<GreenElementRef<'_>> {
+    pub fn text_range(&self) -> TextRange {
+        TextRange::at(self.offset, self.green.text_len())
+    }
+
+    /// Finds this element below `root`, which must be the red node for the
+    /// green node this one was found from.
+    ///
+    /// Elements are told apart by identity and offset. Of several shared
+    /// empty elements at the same offset, the first one is returned.
+    pub fn to_red(&self, root: &red::SyntaxNode) -> Option<red::SyntaxElement> {
+        let green_root = root.green();
+        if is_same(NodeOrToken::Node(&green_root), self.green) && self.offset == 0.into() {
+            return Some(root.clone().into());
+        }
+        let mut path = Vec::new();
+        if !find_path(&green_root, 0.into(), self, &mut path) {
+            return None;
+        }
+        let mut res: red::SyntaxElement = root.clone().into();
+        for index in path {
+            res = res.into_node()?.child_or_token_at_index(index)?;
+        }
+        Some(res)
+    }
+}
+
+fn is_same(lhs: GreenElementRef<'_>, rhs: GreenElementRef<'_>) -> bool {
+    match (lhs, rhs) {
+        (NodeOrToken::Node(lhs), NodeOrToken::Node(rhs)) => ptr::eq(lhs, rhs),
+        (NodeOrToken::Token(lhs), NodeOrToken::Token(rhs)) => ptr::eq(lhs, rhs),
+        _ => false,
+    }
+}
+
+/// Pushes the child indices leading from `node` to `target` onto `path`.
+fn find_path(
+    node: &GreenNodeData,
+    offset: TextSize,
+    target: &GreenAt<GreenElementRef<'_>>,
+    path: &mut Vec<usize>,
+) -> bool {
+    let range = target.text_range();
+    if range.start() < offset {
+        return false;
+    }
+    for (index, child) in node.children_at_offset(range.start() - offset) {
+        let child = GreenAt {
+            green: child.as_ref(),
+            offset: offset + child.rel_offset(),
+        };
+        if !child.text_range().contains_range(range) {
+            continue;
+        }
+        path.push(index);
+        if child.offset == target.offset && is_same(child.green, target.green) {
+            return true;
+        }
+        if let NodeOrToken::Node(it) = child.green {
+            if find_path(it, child.offset, target, path) {
+                return true;
+            }
+        }
+        path.pop();
+    }
+    false
+}
+
+impl GreenNodeData {
+    /// Walks the tree like [`red::SyntaxNode::preorder_with_tokens`].
+    pub fn preorder_with_tokens(&self) -> GreenPreorder<'_> {
+        GreenPreorder {
+            start: Some(self),
+            stack: Vec::new(),
+            leave_token: None,
+            entered_node: false,
+        }
+    }
+
+    /// Like [`red::SyntaxNode::token_at_offset`].
+    pub fn token_at_offset(&self, offset: TextSize) -> TokenAtOffset<GreenAt<&GreenTokenData>> {
+        assert!(
+            offset <= self.text_len(),
+            "Bad offset: len {:?} offset {:?}",
+            self.text_len(),
+            offset
+        );
+        token_at_offset(
+            GreenAt {
+                green: self,
+                offset: 0.into(),
+            },
+            offset,
+        )
+    }
+
+    /// Like [`red::SyntaxNode::covering_element`].
+    pub fn covering_element(&self, range: TextRange) -> GreenAt<GreenElementRef<'_>> {
+        let mut res = GreenAt {
+            green: NodeOrToken::Node(self),
+            offset: 0.into(),
+        };
+        loop {
+            assert!(
+                res.text_range().contains_range(range),
+                "Bad range: node range {:?}, range {:?}",
+                res.text_range(),
+                range,
+            );
+            let node = match res.green {
+                NodeOrToken::Token(_) => return res,
+                NodeOrToken::Node(it) => it,
+            };
+            res = match node.child_at_range(range - res.offset) {
+                Some((_, rel_offset, child)) => GreenAt {
+                    green: child,
+                    offset: res.offset + rel_offset,
+                },
+                None => return res,
+            };
+        }
+    }
+}
+
+fn token_at_offset(
+    mut node: GreenAt<&GreenNodeData>,
+    offset: TextSize,
+) -> TokenAtOffset<GreenAt<&GreenTokenData>> {
+    loop {
+        let mut children = node
+            .green
+            .children_at_offset(offset - node.offset)
+            .map(|(_, child)| GreenAt {
+                green: child.as_ref(),
+                offset: node.offset + child.rel_offset(),
+            })
+            .filter(|child| !child.text_range().is_empty());
+
+        let left = match children.next() {
+            Some(it) => it,
+            None => return TokenAtOffset::None,
+        };
+        if let Some(right) = children.next() {
+            return TokenAtOffset::Between(
+                single_token_at(left, offset),
+                single_token_at(right, offset),
+            );
+        }
+        node = match left.green {
+            NodeOrToken::Token(green) => {
+                return TokenAtOffset::Single(GreenAt {
+                    green,
+                    offset: left.offset,
+                })
+            }
+            NodeOrToken::Node(green) => GreenAt {
+                green,
+                offset: left.offset,
+            },
+        };
+    }
+}
+
+/// The token at `offset` below `element`, which touches `offset` with one
+/// of its ends.
+fn single_token_at<'a>(
+    element: GreenAt<GreenElementRef<'a>>,
+    offset: TextSize,
+) -> GreenAt<&'a GreenTokenData> {
+    let node = match element.green {
+        NodeOrToken::Token(green) => {
+            return GreenAt {
+                green,
+                offset: element.offset,
+            }
+        }
+        NodeOrToken::Node(green) => GreenAt {
+            green,
+            offset: element.offset,
+        },
+    };
+    match token_at_offset(node, offset) {
+        TokenAtOffset::Single(it) => it,
+        _ => unreachable!(),
+    }
+}
+
+/// See [`GreenNodeData::preorder_with_tokens`]. Only allocates the stack of
+/// the nodes being walked.
+#[derive(Debug, Clone)]
+pub struct GreenPreorder<'a> {
+    start: Option<&'a GreenNodeData>,
+    stack: Vec<Frame<'a>>,
+    leave_token: Option<GreenAt<&'a GreenTokenData>>,
+    entered_node: bool,
+}
+
+#[derive(Debug, Clone)]
+struct Frame<'a> {
+    node: GreenAt<&'a GreenNodeData>,
+    children: slice::Iter<'a, GreenChild>,
+}
+
+impl<'a> GreenPreorder<'a> {
+    /// Skips the children of the node entered last.
+    pub fn skip_subtree(&mut self) {
+        if self.entered_node {
+            if let Some(frame) = self.stack.last_mut() {
+                frame.children = [].iter();
+            }
+        }
+    }
+
+    fn enter(
+        &mut self,
+        node: GreenAt<&'a GreenNodeData>,
+    ) -> WalkEvent<GreenAt<GreenElementRef<'a>>> {
+        self.stack.push(Frame {
+            node,
+            children: node.green.children().raw,
+        });
+        self.entered_node = true;
+        WalkEvent::Enter(node.element())
+    }
+}
+
+impl<'a> Iterator for GreenPreorder<'a> {
+    type Item = WalkEvent<GreenAt<GreenElementRef<'a>>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.entered_node = false;
+        if let Some(green) = self.start.take() {
+            let offset = 0.into();
+            return Some(self.enter(GreenAt { green, offset }));
+        }
+        if let Some(token) = self.leave_token.take() {
+            return Some(WalkEvent::Leave(token.element()));
+        }
+        let frame = self.stack.last_mut()?;
+        let child = match frame.children.next() {
+            Some(it) => it,
+            None => {
+                let node = self.stack.pop().unwrap().node;
+                return Some(WalkEvent::Leave(node.element()));
+            }
+        };
+        let offset = frame.node.offset + child.rel_offset();
+        Some(match child.as_ref() {
+            NodeOrToken::Node(green) => self.enter(GreenAt { green, offset }),
+            NodeOrToken::Token(green) => {
+                let token = GreenAt { green, offset };
+                self.leave_token = Some(token);
+                WalkEvent::Enter(token.element())
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{GreenNode, GreenNodeBuilder, SyntaxKind};
+
+    use super::*;
+
+    // ROOT(A("ab" "c") B() "d")
+    fn tree() -> GreenNode {
+        let mut builder = GreenNodeBuilder::new();
+        builder.start_node(SyntaxKind(0));
+        builder.start_node(SyntaxKind(1));
+        builder.token(SyntaxKind(10), "ab");
+        builder.token(SyntaxKind(10), "c");
+        builder.finish_node();
+        builder.start_node(SyntaxKind(2));
+        builder.finish_node();
+        builder.token(SyntaxKind(10), "d");
+        builder.finish_node();
+        builder.finish()
+    }
+
+    #[test]
+    fn preorder_matches_red() {
+        let green = tree();
+        let root = red::SyntaxNode::new_root(green.clone());
+        let red_events = root
+            .preorder_with_tokens()
+            .map(|event| event.map(|it| (it.kind(), it.text_range())));
+        let green_events = green
+            .preorder_with_tokens()
+            .map(|event| event.map(|it| (it.green.kind(), it.text_range())));
+        let fmt = |it: WalkEvent<_>| format!("{it:?}");
+        assert!(red_events.map(fmt).eq(green_events.map(fmt)));
+
+        for event in green.preorder_with_tokens() {
+            if let WalkEvent::Enter(it) = event {
+                let red = it.to_red(&root).unwrap();
+                assert_eq!(
+                    (red.kind(), red.text_range()),
+                    (it.green.kind(), it.text_range())
+                );
+            }
+        }
+
+        let mut preorder = green.preorder_with_tokens();
+        preorder.next();
+        preorder.next();
+        preorder.skip_subtree();
+        let kinds = preorder.filter_map(|event| match event {
+            WalkEvent::Enter(it) => Some(it.green.kind()),
+            WalkEvent::Leave(_) => None,
+        });
+        assert!(kinds.eq([SyntaxKind(2), SyntaxKind(10)]));
+    }
+
+    #[test]
+    fn offset_lookup() {
+        let green = tree();
+        let root = red::SyntaxNode::new_root(green.clone());
+        for offset in 0..=4 {
+            let offset = TextSize::from(offset);
+            let tokens = green
+                .token_at_offset(offset)
+                .map(|it| it.to_red(&root).unwrap());
+            assert!(tokens.eq(root.token_at_offset(offset)));
+        }
+
+        let range = TextRange::new(1.into(), 3.into());
+        let covering = green.covering_element(range);
+        assert_eq!(covering.green.kind(), SyntaxKind(1));
+        assert_eq!(covering.to_red(&root), Some(root.covering_element(range)));
+        let empty = green.covering_element(TextRange::empty(3.into()));
+        assert_eq!(
+            empty.to_red(&root),
+            Some(root.covering_element(TextRange::empty(3.into())))
+        );
+    }
+}
@@ -0,0 +1,302 @@
+//! The lexing contract shared by parsers and incremental relexing.
+//!
+//! A [`Lexer`] splits text into raw `(kind, len)` tokens, trivia included.
+//! [`tokenize`] runs it over a whole text and checks that the tokens cover
+//! it exactly. [`TokenStream`] layers lookahead, trivia skipping and
+//! joint-ness on top and can be fed to a [`Parser`](crate::parser::Parser).
+//! [`Lexer::lex_single`] adapts a lexer to the closures taken by
+//! [`GreenTokenData::relex`](crate::GreenTokenData::relex).
+
+use std::{error::Error, fmt};
+
+use crate::{
+    parser::{self, TokenSource},
+    Language, SyntaxKind, TextRange, TextSize,
+};
+
+pub trait Lexer {
+    /// Lexes the first token of `text`, which is never empty, returning its
+    /// kind and length. The length must be non-zero and end on a char
+    /// boundary within `text`.
+    fn next_token(&mut self, text: &str) -> (SyntaxKind, TextSize);
+
+    /// Returns the kind of `text` if it is exactly one token.
+    fn lex_single(&mut self, text: &str) -> Option<SyntaxKind> {
+        if text.is_empty() {
+            return None;
+        }
+        let (kind, len) = self.next_token(text);
+        if len != TextSize::of(text) {
+            return None;
+        }
+        Some(kind)
+    }
+}
+
+impl<F: FnMut(&str) -> (SyntaxKind, TextSize)> Lexer for F {
+    fn next_token(&mut self, text: &str) -> (SyntaxKind, TextSize) {
+        self(text)
+    }
+}
+
+/// A token length returned by a [`Lexer`] which breaks the contract.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LexError {
+    EmptyToken {
+        offset: TextSize,
+    },
+    /// The token extends past the end of the text.
+    PastEnd {
+        offset: TextSize,
+        len: TextSize,
+    },
+    /// The token ends inside of a character.
+    NotCharBoundary {
+        offset: TextSize,
+        len: TextSize,
+    },
+}
+
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LexError::EmptyToken { offset } => write!(f, "empty token at {offset:?}"),
+            LexError::PastEnd { offset, len } => {
+                write!(
+                    f,
+                    "token at {offset:?} of length {len:?} extends past the end"
+                )
+            }
+            LexError::NotCharBoundary { offset, len } => {
+                write!(
+                    f,
+                    "token at {offset:?} of length {len:?} ends inside a character"
+                )
+            }
+        }
+    }
+}
+
+impl Error for LexError {}
+
+/// Splits the whole of `text` into raw tokens.
+pub fn tokenize<L: Lexer + ?Sized>(
+    lexer: &mut L,
+    text: &str,
+) -> Result<Vec<(SyntaxKind, TextSize)>, LexError> {
+    let mut res = Vec::new();
+    let mut offset = TextSize::from(0);
+    while offset < TextSize::of(text) {
+        let rest = &text[usize::from(offset)..];
+        let (kind, len) = lexer.next_token(rest);
+        if len == 0.into() {
+            return Err(LexError::EmptyToken { offset });
+        }
+        if len > TextSize::of(rest) {
+            return Err(LexError::PastEnd { offset, len });
+        }
+        if !rest.is_char_boundary(len.into()) {
+            return Err(LexError::NotCharBoundary { offset, len });
+        }
+        res.push((kind, len));
+        offset += len;
+    }
+    Ok(res)
+}
+
+/// The tokens of a text, with lookahead over the significant ones.
+#[derive(Debug, Clone)]
+pub struct TokenStream<'a> {
+    text: &'a str,
+    raw: Vec<(SyntaxKind, TextSize)>,
+    significant: Vec<Significant>,
+    pos: usize,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Significant {
+    kind: SyntaxKind,
+    range: TextRange,
+    /// Whether the next significant token immediately follows this one.
+    joint: bool,
+}
+
+impl<'a> TokenStream<'a> {
+    /// Lexes `text`, telling trivia apart with [`Language::is_trivia`].
+    pub fn new<L: Language>(
+        text: &'a str,
+        lexer: &mut (impl Lexer + ?Sized),
+    ) -> Result<TokenStream<'a>, LexError> {
+        let raw = tokenize(lexer, text)?;
+        Ok(TokenStream::from_raw::<L>(text, raw))
+    }
+
+    /// Wraps tokens lexed elsewhere. Panics if their lengths don't add up to
+    /// the length of `text`.
+    pub fn from_raw<L: Language>(
+        text: &'a str,
+        raw: Vec<(SyntaxKind, TextSize)>,
+    ) -> TokenStream<'a> {
+        let mut offset = TextSize::from(0);
+        let ranges = raw
+            .iter()
+            .map(|&(_, len)| {
+                let range = TextRange::at(offset, len);
+                offset = range.end();
+                range
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            offset,
+            TextSize::of(text),
+            "token lengths don't add up to the text length"
+        );
+        let significant = parser::significant_tokens::<L>(&raw)
+            .map(|(idx, joint)| Significant {
+                kind: raw[idx].0,
+                range: ranges[idx],
+                joint,
+            })
+            .collect();
+        TokenStream {
+            text,
+            raw,
+            significant,
+            pos: 0,
+        }
+    }
+
+    pub fn text(&self) -> &'a str {
+        self.text
+    }
+
+    /// All tokens, trivia included, for [`build_tree`](crate::parser::build_tree).
+    pub fn raw_tokens(&self) -> &[(SyntaxKind, TextSize)] {
+        &self.raw
+    }
+
+    /// Range of the `n`-th significant token ahead.
+    pub fn nth_range(&self, n: usize) -> Option<TextRange> {
+        self.significant.get(self.pos + n).map(|it| it.range)
+    }
+
+    /// Text of the `n`-th significant token ahead.
+    pub fn nth_text(&self, n: usize) -> Option<&'a str> {
+        self.nth_range(n).map(|range| &self.text[range])
+    }
+}
+
+impl TokenSource for TokenStream<'_> {
+    fn nth(&self, n: usize) -> Option<SyntaxKind> {
+        self.significant.get(self.pos + n).map(|it| it.kind)
+    }
+
+    fn is_joint(&self, n: usize) -> bool {
+        self.significant
+            .get(self.pos + n)
+            .is_some_and(|it| it.joint)
+    }
+
+    fn bump(&mut self) {
+        if self.pos < self.significant.len() {
+            self.pos += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        parser::{build_tree, Parser},
+        test_utils::{TestLang, WS},
+        GreenNodeBuilder, GreenToken, Indel,
+    };
+
+    use super::*;
+
+    const IDENT: SyntaxKind = SyntaxKind(1);
+    const GT: SyntaxKind = SyntaxKind(2);
+    const SHR: SyntaxKind = SyntaxKind(3);
+    const ROOT: SyntaxKind = SyntaxKind(4);
+
+    fn lex(text: &str) -> (SyntaxKind, TextSize) {
+        let first = text.chars().next().unwrap();
+        let (kind, pred): (_, fn(char) -> bool) = match first {
+            ' ' => (WS, |c| c == ' '),
+            '>' => (GT, |_| false),
+            _ => (IDENT, |c| c.is_alphanumeric()),
+        };
+        let len = first.len_utf8()
+            + text[first.len_utf8()..]
+                .find(|c| !pred(c))
+                .unwrap_or(text.len() - first.len_utf8());
+        (kind, TextSize::from(len as u32))
+    }
+
+    #[test]
+    fn token_stream() {
+        let text = "a>> b> >";
+        let stream = TokenStream::new::<TestLang>(text, &mut lex).unwrap();
+        assert_eq!(stream.raw_tokens().len(), 8);
+        assert_eq!(stream.nth(3), Some(IDENT));
+        assert_eq!(stream.nth_text(3), Some("b"));
+        let joints = (0..6).map(|n| stream.is_joint(n)).collect::<Vec<_>>();
+        assert_eq!(joints, [true, true, false, true, false, false]);
+
+        let mut p = Parser::new(stream.clone());
+        let root = p.start();
+        while !p.at_end() {
+            if p.at(GT) && p.is_joint(0) && p.nth(1) == Some(GT) {
+                p.bump_glued(SHR, 2);
+            } else {
+                p.bump();
+            }
+        }
+        root.complete(&mut p, ROOT);
+        let (green, _) = build_tree::<TestLang>(
+            p.finish(),
+            text,
+            stream.raw_tokens(),
+            GreenNodeBuilder::new(),
+        );
+        let tokens = green.children().map(|it| {
+            let token = it.into_token().unwrap();
+            (token.kind(), token.text().to_string())
+        });
+        let expected = [
+            (IDENT, "a"),
+            (SHR, ">>"),
+            (WS, " "),
+            (IDENT, "b"),
+            (GT, ">"),
+            (WS, " "),
+            (GT, ">"),
+        ];
+        assert!(tokens.eq(expected.map(|(kind, text)| (kind, text.to_string()))));
+    }
+
+    #[test]
+    fn lexing_contract() {
+        assert_eq!(
+            tokenize(&mut |_: &str| (WS, TextSize::from(0)), "x"),
+            Err(LexError::EmptyToken { offset: 0.into() })
+        );
+        assert_eq!(
+            tokenize(&mut |_: &str| (WS, TextSize::from(1)), "é"),
+            Err(LexError::NotCharBoundary {
+                offset: 0.into(),
+                len: 1.into()
+            })
+        );
+
+        let token = GreenToken::new(IDENT, "ab");
+        let relexed = token.relex(&Indel::insert(1.into(), "c".into()), |text| {
+            lex.lex_single(text)
+        });
+        assert_eq!(relexed.unwrap().text(), "acb");
+        let relexed = token.relex(&Indel::insert(1.into(), " ".into()), |text| {
+            lex.lex_single(text)
+        });
+        assert!(relexed.is_err());
+    }
+}
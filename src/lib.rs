@@ -20,9 +20,25 @@ mod green;
 pub mod red;
 
 pub mod api;
+pub mod diff;
+mod dump;
+pub mod lexer;
+mod line_index;
+mod parse;
+pub mod parser;
+pub mod query;
+pub mod snapshot;
 mod syntax_text;
+pub mod ted;
+mod text_edit;
+mod trivia;
 mod utility_types;
 
+#[cfg(feature = "serde")]
+mod serde_impls;
+#[cfg(test)]
+mod test_utils;
+
 #[allow(unsafe_code)]
 mod arc;
 pub mod ast;
@@ -36,10 +52,16 @@ pub use crate::{
     api::{
         Language, SyntaxElement, SyntaxElementChildren, SyntaxNode, SyntaxNodeChildren, SyntaxToken,
     },
+    dump::{kind_to_string, parse_dump, DumpError},
     green::{
-        Checkpoint, Children, GreenNode, GreenNodeBuilder, GreenNodeData, GreenToken,
-        GreenTokenData, NodeCache, SyntaxKind,
+        AllocCounts, Checkpoint, Children, DecodeError, GreenAt, GreenNode, GreenNodeBuilder,
+        GreenNodeData, GreenPreorder, GreenToken, GreenTokenData, InternPolicy, MemoryReport,
+        NodeCache, NodeCacheStats, RelexError, SharedNodeCache, SyntaxKind,
     },
+    line_index::{ColumnEncoding, LineCol, LineIndex},
+    parse::{Parse, SyntaxError},
     syntax_text::SyntaxText,
+    text_edit::{Indel, TextEdit},
+    trivia::TriviaAttachment,
     utility_types::{Direction, NodeOrToken, TokenAtOffset, WalkEvent},
 };
@@ -0,0 +1,286 @@
+//! Conversions between `TextSize` offsets and line/column positions.
+
+use std::ops::Range;
+
+use crate::{SyntaxText, TextRange, TextSize};
+
+/// How columns are counted within a line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ColumnEncoding {
+    /// Columns are byte offsets from the start of the line.
+    Utf8,
+    /// Columns count UTF-16 code units, as used by LSP.
+    Utf16,
+    /// Columns count Unicode scalar values.
+    Utf32,
+}
+
+/// Zero-based line and column.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct LineCol {
+    pub line: u32,
+    pub col: u32,
+}
+
+/// Maps offsets in a text to line/column positions and back.
+///
+/// Lines are separated by `\n`. Each lookup is a couple of binary searches,
+/// so it is `O(log n)` in the size of the text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineIndex {
+    /// Offset of the start of each line; the first one is always zero.
+    line_starts: Vec<TextSize>,
+    /// All non-ASCII characters of the text, in order.
+    wide_chars: Vec<WideChar>,
+    /// `utf16_shrink[i]` is how many more UTF-8 bytes than UTF-16 code units
+    /// the first `i` wide chars take, `utf32_shrink` likewise.
+    utf16_shrink: Vec<u32>,
+    utf32_shrink: Vec<u32>,
+    len: TextSize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct WideChar {
+    start: TextSize,
+    len_utf8: u8,
+    len_utf16: u8,
+}
+
+impl WideChar {
+    fn end(&self) -> TextSize {
+        self.start + TextSize::from(self.len_utf8 as u32)
+    }
+
+    fn shrink(&self, encoding: ColumnEncoding) -> u32 {
+        let len_utf8 = self.len_utf8 as u32;
+        match encoding {
+            ColumnEncoding::Utf8 => 0,
+            ColumnEncoding::Utf16 => len_utf8 - self.len_utf16 as u32,
+            ColumnEncoding::Utf32 => len_utf8 - 1,
+        }
+    }
+}
+
+impl LineIndex {
+    pub fn new(text: &str) -> LineIndex {
+        let mut res = LineIndex::empty();
+        res.push_chunk(text);
+        res
+    }
+
+    /// Builds the index from the text of a tree without collecting it into a
+    /// `String` first.
+    pub fn from_syntax_text(text: &SyntaxText) -> LineIndex {
+        let mut res = LineIndex::empty();
+        text.for_each_chunk(|chunk| res.push_chunk(chunk));
+        res
+    }
+
+    fn empty() -> LineIndex {
+        LineIndex {
+            line_starts: vec![0.into()],
+            wide_chars: Vec::new(),
+            utf16_shrink: vec![0],
+            utf32_shrink: vec![0],
+            len: 0.into(),
+        }
+    }
+
+    fn push_chunk(&mut self, chunk: &str) {
+        for (i, c) in chunk.char_indices() {
+            let offset = self.len + TextSize::from(i as u32);
+            if c == '\n' {
+                self.line_starts.push(offset + TextSize::of('\n'));
+            } else if !c.is_ascii() {
+                let wide = WideChar {
+                    start: offset,
+                    len_utf8: c.len_utf8() as u8,
+                    len_utf16: c.len_utf16() as u8,
+                };
+                self.utf16_shrink
+                    .push(self.utf16_shrink.last().unwrap() + wide.shrink(ColumnEncoding::Utf16));
+                self.utf32_shrink
+                    .push(self.utf32_shrink.last().unwrap() + wide.shrink(ColumnEncoding::Utf32));
+                self.wide_chars.push(wide);
+            }
+        }
+        self.len += TextSize::of(chunk);
+    }
+
+    /// Length of the indexed text.
+    pub fn len(&self) -> TextSize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0.into()
+    }
+
+    /// Number of lines; a text always has at least one.
+    pub fn line_count(&self) -> u32 {
+        self.line_starts.len() as u32
+    }
+
+    /// Range of the given line, including its trailing `\n` if any.
+    pub fn line_range(&self, line: u32) -> Option<TextRange> {
+        let start = *self.line_starts.get(line as usize)?;
+        let end = self
+            .line_starts
+            .get(line as usize + 1)
+            .copied()
+            .unwrap_or(self.len);
+        Some(TextRange::new(start, end))
+    }
+
+    /// Returns `None` if `offset` is past the end of the text or inside a
+    /// multi-byte character.
+    pub fn line_col(&self, offset: TextSize, encoding: ColumnEncoding) -> Option<LineCol> {
+        if offset > self.len {
+            return None;
+        }
+        let line = self.line_starts.partition_point(|&it| it <= offset) - 1;
+        let line_start = self.line_starts[line];
+
+        let first = self.wide_chars.partition_point(|it| it.start < line_start);
+        let last = self.wide_chars.partition_point(|it| it.start < offset);
+        if last > first && self.wide_chars[last - 1].end() > offset {
+            return None;
+        }
+        let col = u32::from(offset - line_start) - self.shrink(first..last, encoding);
+        Some(LineCol {
+            line: line as u32,
+            col,
+        })
+    }
+
+    /// Returns `None` if the line does not exist, or the column is past the
+    /// end of the line or inside a character.
+    pub fn offset(&self, line_col: LineCol, encoding: ColumnEncoding) -> Option<TextSize> {
+        let range = self.line_range(line_col.line)?;
+        let line_start = range.start();
+        let first = self.wide_chars.partition_point(|it| it.start < line_start);
+        let end = self.wide_chars.partition_point(|it| it.start < range.end());
+
+        // Column at which the wide char `idx` starts.
+        let col_of = |idx: usize| {
+            u32::from(self.wide_chars[idx].start - line_start) - self.shrink(first..idx, encoding)
+        };
+        // Number of wide chars starting before the column, found by binary
+        // search as columns grow monotonically with the index.
+        let (mut last, mut hi) = (first, end);
+        while last < hi {
+            let mid = last + (hi - last) / 2;
+            if col_of(mid) < line_col.col {
+                last = mid + 1;
+            } else {
+                hi = mid;
+            }
+        }
+        if last > first {
+            let wide = self.wide_chars[last - 1];
+            let len = wide.len_utf8 as u32 - wide.shrink(encoding);
+            if col_of(last - 1) + len > line_col.col {
+                return None;
+            }
+        }
+        // Columns come from clients, so they may be arbitrarily large.
+        let col = line_col
+            .col
+            .checked_add(self.shrink(first..last, encoding))?;
+        let offset = line_start.checked_add(TextSize::from(col))?;
+        let line_end = match self.line_starts.get(line_col.line as usize + 1) {
+            Some(_) => range.end() - TextSize::of('\n'),
+            None => range.end(),
+        };
+        if offset > line_end {
+            return None;
+        }
+        Some(offset)
+    }
+
+    /// Maps both ends of `range` at once.
+    pub fn line_col_range(
+        &self,
+        range: TextRange,
+        encoding: ColumnEncoding,
+    ) -> Option<Range<LineCol>> {
+        let start = self.line_col(range.start(), encoding)?;
+        let end = self.line_col(range.end(), encoding)?;
+        Some(start..end)
+    }
+
+    /// Inverse of [`LineIndex::line_col_range`].
+    pub fn text_range(&self, range: Range<LineCol>, encoding: ColumnEncoding) -> Option<TextRange> {
+        let start = self.offset(range.start, encoding)?;
+        let end = self.offset(range.end, encoding)?;
+        if start > end {
+            return None;
+        }
+        Some(TextRange::new(start, end))
+    }
+
+    /// How many more bytes than columns the wide chars in `range` take.
+    fn shrink(&self, range: Range<usize>, encoding: ColumnEncoding) -> u32 {
+        let prefix = match encoding {
+            ColumnEncoding::Utf8 => return 0,
+            ColumnEncoding::Utf16 => &self.utf16_shrink,
+            ColumnEncoding::Utf32 => &self.utf32_shrink,
+        };
+        prefix[range.end] - prefix[range.start]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn line_col_roundtrip() {
+        let text = "fn main() {\n    \"yé𝄞\" x\n}";
+        let index = LineIndex::new(text);
+        assert_eq!(index.line_count(), 3);
+
+        let x = TextSize::from(text.find('x').unwrap() as u32);
+        let check = |encoding, col| {
+            let line_col = LineCol { line: 1, col };
+            assert_eq!(index.line_col(x, encoding), Some(line_col));
+            assert_eq!(index.offset(line_col, encoding), Some(x));
+        };
+        check(ColumnEncoding::Utf8, 14);
+        check(ColumnEncoding::Utf16, 11);
+        check(ColumnEncoding::Utf32, 10);
+
+        let in_clef = TextSize::from(text.find('𝄞').unwrap() as u32 + 1);
+        assert_eq!(index.line_col(in_clef, ColumnEncoding::Utf16), None);
+        let mid_surrogate = LineCol { line: 1, col: 8 };
+        assert_eq!(index.offset(mid_surrogate, ColumnEncoding::Utf16), None);
+        assert_eq!(
+            index.offset(LineCol { line: 0, col: 12 }, ColumnEncoding::Utf8),
+            None
+        );
+        assert_eq!(
+            index.offset(LineCol { line: 3, col: 0 }, ColumnEncoding::Utf8),
+            None
+        );
+        for encoding in [ColumnEncoding::Utf8, ColumnEncoding::Utf16] {
+            for line in 0..3 {
+                let line_col = LineCol {
+                    line,
+                    col: u32::MAX,
+                };
+                assert_eq!(index.offset(line_col, encoding), None);
+            }
+        }
+
+        let range = TextRange::new(0.into(), index.len());
+        let line_cols = index.line_col_range(range, ColumnEncoding::Utf16).unwrap();
+        assert_eq!(
+            line_cols,
+            LineCol { line: 0, col: 0 }..LineCol { line: 2, col: 1 }
+        );
+        assert_eq!(
+            index.text_range(line_cols, ColumnEncoding::Utf16),
+            Some(range)
+        );
+    }
+}
@@ -0,0 +1,231 @@
+//! The result of parsing: a tree together with the errors found in it.
+
+use std::{error::Error, fmt, marker::PhantomData, sync::Arc};
+
+use crate::{ast::AstNode, GreenNode, Language, SyntaxNode, TextRange};
+
+/// An error message attached to a range of the source text.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SyntaxError {
+    message: String,
+    range: TextRange,
+}
+
+impl SyntaxError {
+    pub fn new(message: impl Into<String>, range: TextRange) -> SyntaxError {
+        SyntaxError {
+            message: message.into(),
+            range,
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    pub fn range(&self) -> TextRange {
+        self.range
+    }
+
+    pub fn with_range(mut self, range: TextRange) -> SyntaxError {
+        self.range = range;
+        self
+    }
+}
+
+impl fmt::Display for SyntaxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.message.fmt(f)
+    }
+}
+
+impl Error for SyntaxError {}
+
+/// A green tree and the errors found while building or validating it.
+///
+/// `T` is the typed root, usually an [`AstNode`], or a [`SyntaxNode`] for an
+/// untyped result. Untyped results are created with [`Parse::new`], and typed
+/// ones from them with [`Parse::cast`], which checks the kind of the root.
+/// Cloning is cheap: the tree and the errors are shared.
+pub struct Parse<T> {
+    green: GreenNode,
+    errors: Arc<[SyntaxError]>,
+    _ty: PhantomData<fn() -> T>,
+}
+
+impl<T> Clone for Parse<T> {
+    fn clone(&self) -> Parse<T> {
+        Parse {
+            green: self.green.clone(),
+            errors: self.errors.clone(),
+            _ty: PhantomData,
+        }
+    }
+}
+
+impl<T> fmt::Debug for Parse<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Parse")
+            .field("green", &self.green)
+            .field("errors", &self.errors)
+            .finish()
+    }
+}
+
+impl<T> Parse<T> {
+    /// Errors are kept sorted by the start of their range.
+    fn from_parts(green: GreenNode, errors: Vec<SyntaxError>) -> Parse<T> {
+        let mut errors = errors;
+        errors.sort_by_key(|it| it.range.start());
+        Parse {
+            green,
+            errors: errors.into(),
+            _ty: PhantomData,
+        }
+    }
+
+    pub fn green(&self) -> &GreenNode {
+        &self.green
+    }
+
+    pub fn errors(&self) -> &[SyntaxError] {
+        &self.errors
+    }
+
+    pub fn has_errors(&self) -> bool {
+        !self.errors.is_empty()
+    }
+
+    /// Adds errors found by a later pass, such as validation.
+    pub fn with_errors(self, errors: impl IntoIterator<Item = SyntaxError>) -> Parse<T> {
+        let mut errors = errors.into_iter().peekable();
+        if errors.peek().is_none() {
+            return self;
+        }
+        let merged = self.errors.iter().cloned().chain(errors).collect();
+        Parse::from_parts(self.green, merged)
+    }
+}
+
+impl<L: Language> Parse<SyntaxNode<L>> {
+    /// Creates an untyped result. Errors are kept sorted by the start of their
+    /// range.
+    pub fn new(green: GreenNode, errors: Vec<SyntaxError>) -> Parse<SyntaxNode<L>> {
+        Parse::from_parts(green, errors)
+    }
+
+    pub fn syntax_node(&self) -> SyntaxNode<L> {
+        SyntaxNode::new_root(self.green.clone())
+    }
+
+    /// Converts to a typed result, if the root has the right kind.
+    pub fn cast<N: AstNode<Language = L>>(self) -> Option<Parse<N>> {
+        N::cast(self.syntax_node())?;
+        Some(Parse {
+            green: self.green,
+            errors: self.errors,
+            _ty: PhantomData,
+        })
+    }
+}
+
+impl<T: AstNode> Parse<T> {
+    pub fn syntax_node(&self) -> SyntaxNode<T::Language> {
+        SyntaxNode::new_root(self.green.clone())
+    }
+
+    /// Forgets the type of the root.
+    pub fn to_syntax(self) -> Parse<SyntaxNode<T::Language>> {
+        Parse {
+            green: self.green,
+            errors: self.errors,
+            _ty: PhantomData,
+        }
+    }
+
+    /// The typed root of the tree. [`Parse::cast`] checked that the root
+    /// can be cast.
+    pub fn tree(&self) -> T {
+        T::cast(self.syntax_node()).unwrap()
+    }
+
+    /// Runs `validate` on the typed root and adds the errors it returns.
+    pub fn validate<F>(self, validate: F) -> Parse<T>
+    where
+        F: FnOnce(&T) -> Vec<SyntaxError>,
+    {
+        let errors = validate(&self.tree());
+        self.with_errors(errors)
+    }
+
+    /// Returns the typed root if there were no errors.
+    pub fn ok(self) -> Result<T, Vec<SyntaxError>> {
+        if self.errors.is_empty() {
+            Ok(self.tree())
+        } else {
+            Err(self.errors.to_vec())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{test_utils::TestLang, GreenNodeBuilder, SyntaxKind};
+
+    use super::*;
+
+    struct Root(SyntaxNode<TestLang>);
+
+    impl AstNode for Root {
+        type Language = TestLang;
+
+        fn can_cast(kind: SyntaxKind) -> bool {
+            kind == SyntaxKind(0)
+        }
+        fn cast(node: SyntaxNode<TestLang>) -> Option<Root> {
+            Root::can_cast(node.kind()).then(|| Root(node))
+        }
+        fn syntax(&self) -> &SyntaxNode<TestLang> {
+            &self.0
+        }
+    }
+
+    #[test]
+    fn errors_stay_attached() {
+        let mut builder = GreenNodeBuilder::new();
+        builder.start_node(SyntaxKind(0));
+        builder.token(SyntaxKind(1), "hello");
+        builder.finish_node();
+        let range = |start: u32, end: u32| TextRange::new(start.into(), end.into());
+
+        let parse: Parse<SyntaxNode<TestLang>> = Parse::new(
+            builder.finish(),
+            vec![SyntaxError::new("late", range(3, 4))],
+        );
+        let parse = parse.cast::<Root>().unwrap();
+        let copy = parse.clone();
+        let parse = parse.validate(|root| {
+            assert_eq!(root.syntax().text(), "hello");
+            vec![SyntaxError::new("early", range(0, 1))]
+        });
+
+        let messages = parse
+            .errors()
+            .iter()
+            .map(|it| it.message())
+            .collect::<Vec<_>>();
+        assert_eq!(messages, ["early", "late"]);
+        assert_eq!(copy.errors().len(), 1);
+        assert_eq!(copy.green(), parse.green());
+        assert!(copy.ok().is_err());
+    }
+
+    #[test]
+    fn cast_checks_the_root_kind() {
+        let mut builder = GreenNodeBuilder::new();
+        builder.start_node(SyntaxKind(1));
+        builder.finish_node();
+        let parse: Parse<SyntaxNode<TestLang>> = Parse::new(builder.finish(), Vec::new());
+        assert!(parse.cast::<Root>().is_none());
+    }
+}
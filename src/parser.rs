@@ -0,0 +1,541 @@
+//! Event-based infrastructure for recursive-descent parsers.
+//!
+//! A [`Parser`] does not build a tree directly. Instead, it records a flat
+//! list of [`Event`]s: node starts, node finishes, significant tokens and
+//! errors. Trivia is invisible to the parser. [`build_tree`] then replays the
+//! events together with the full token list into a [`GreenNodeBuilder`],
+//! placing trivia between nodes.
+//!
+//! Working with events rather than a builder allows wrapping an already
+//! completed node into a new parent via [`CompletedMarker::precede`], which is
+//! what left-associative binary expressions need:
+//!
+//! ```
+//! # use rowan_test::{parser::{build_tree, Parser, RawTokens}, GreenNodeBuilder, Language, SyntaxKind};
+//! # #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+//! # enum Calc {}
+//! # impl Language for Calc {
+//! #     type Kind = SyntaxKind;
+//! #     fn kind_from_raw(raw: SyntaxKind) -> SyntaxKind { raw }
+//! #     fn kind_to_raw(kind: SyntaxKind) -> SyntaxKind { kind }
+//! # }
+//! # const NUM: SyntaxKind = SyntaxKind(0);
+//! # const PLUS: SyntaxKind = SyntaxKind(1);
+//! # const BIN: SyntaxKind = SyntaxKind(2);
+//! # const LIT: SyntaxKind = SyntaxKind(3);
+//! let tokens = [(NUM, 1.into()), (PLUS, 1.into()), (NUM, 1.into())];
+//! let mut p = Parser::new(RawTokens::new::<Calc>(&tokens));
+//! let m = p.start();
+//! p.bump();
+//! let mut lhs = m.complete(&mut p, LIT);
+//! while p.eat(PLUS) {
+//!     let m = lhs.precede(&mut p);
+//!     let rhs = p.start();
+//!     p.bump();
+//!     rhs.complete(&mut p, LIT);
+//!     lhs = m.complete(&mut p, BIN);
+//! }
+//! let (green, errors) = build_tree::<Calc>(p.finish(), "1+2", &tokens, GreenNodeBuilder::new());
+//! assert_eq!(green.kind(), BIN);
+//! assert!(errors.is_empty());
+//! ```
+
+use std::{iter, marker::PhantomData, mem};
+
+use crate::{GreenNode, GreenNodeBuilder, Language, SyntaxError, SyntaxKind, TextRange, TextSize};
+
+/// The significant tokens a [`Parser`] consumes.
+pub trait TokenSource {
+    /// Kind of the `n`-th token ahead, or `None` past the end of input.
+    fn nth(&self, n: usize) -> Option<SyntaxKind>;
+
+    /// Whether the `n`-th token ahead is immediately followed by the next
+    /// one, without trivia in between. Tells `>>` apart from `> >`. Sources
+    /// which don't track it report no token as joint.
+    fn is_joint(&self, n: usize) -> bool {
+        let _ = n;
+        false
+    }
+
+    /// Advances to the next token.
+    fn bump(&mut self);
+}
+
+/// A [`TokenSource`] over a list of raw `(kind, len)` tokens, skipping
+/// trivia as classified by [`Language::is_trivia`].
+#[derive(Debug, Clone)]
+pub struct RawTokens {
+    /// Kinds of the significant tokens, and their joint-ness.
+    kinds: Vec<(SyntaxKind, bool)>,
+    pos: usize,
+}
+
+impl RawTokens {
+    pub fn new<L: Language>(tokens: &[(SyntaxKind, TextSize)]) -> RawTokens {
+        let kinds = significant_tokens::<L>(tokens)
+            .map(|(idx, joint)| (tokens[idx].0, joint))
+            .collect();
+        RawTokens { kinds, pos: 0 }
+    }
+}
+
+pub(crate) fn is_trivia<L: Language>(kind: SyntaxKind) -> bool {
+    L::is_trivia(L::kind_from_raw(kind))
+}
+
+/// Indices of the significant tokens among `tokens`, each with whether the
+/// next significant token follows it without trivia in between.
+pub(crate) fn significant_tokens<L: Language>(
+    tokens: &[(SyntaxKind, TextSize)],
+) -> impl Iterator<Item = (usize, bool)> + '_ {
+    let mut significant = tokens
+        .iter()
+        .enumerate()
+        .filter(|(_, &(kind, _))| !is_trivia::<L>(kind))
+        .map(|(idx, _)| idx)
+        .peekable();
+    iter::from_fn(move || {
+        let idx = significant.next()?;
+        let joint = significant.peek() == Some(&(idx + 1));
+        Some((idx, joint))
+    })
+}
+
+impl TokenSource for RawTokens {
+    fn nth(&self, n: usize) -> Option<SyntaxKind> {
+        self.kinds.get(self.pos + n).map(|&(kind, _)| kind)
+    }
+
+    fn is_joint(&self, n: usize) -> bool {
+        self.kinds
+            .get(self.pos + n)
+            .is_some_and(|&(_, joint)| joint)
+    }
+
+    fn bump(&mut self) {
+        self.pos += 1;
+    }
+}
+
+/// One step of a parse, replayed by [`build_tree`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    /// Starts a node. If `forward_parent` is set, the node at that many events
+    /// ahead becomes the parent of this one, see [`CompletedMarker::precede`].
+    Start {
+        kind: SyntaxKind,
+        forward_parent: Option<u32>,
+    },
+    Finish,
+    /// Consumes the next `n_raw_tokens` significant tokens, which are joint,
+    /// as a single token of kind `kind`.
+    Token {
+        kind: SyntaxKind,
+        n_raw_tokens: u8,
+    },
+    Error {
+        message: String,
+    },
+    /// An abandoned start, or one already replayed as a forward parent.
+    Tombstone,
+}
+
+/// Records events for the tokens of a [`TokenSource`].
+pub struct Parser<S> {
+    source: S,
+    events: Vec<Event>,
+}
+
+impl<S: TokenSource> Parser<S> {
+    pub fn new(source: S) -> Parser<S> {
+        Parser {
+            source,
+            events: Vec::new(),
+        }
+    }
+
+    pub fn finish(self) -> Vec<Event> {
+        self.events
+    }
+
+    /// Kind of the current token, or `None` at the end of input.
+    pub fn current(&self) -> Option<SyntaxKind> {
+        self.nth(0)
+    }
+
+    pub fn nth(&self, n: usize) -> Option<SyntaxKind> {
+        self.source.nth(n)
+    }
+
+    pub fn at(&self, kind: SyntaxKind) -> bool {
+        self.current() == Some(kind)
+    }
+
+    pub fn at_end(&self) -> bool {
+        self.current().is_none()
+    }
+
+    /// See [`TokenSource::is_joint`].
+    pub fn is_joint(&self, n: usize) -> bool {
+        self.source.is_joint(n)
+    }
+
+    /// Starts a new node, which must be either completed or abandoned.
+    pub fn start(&mut self) -> Marker {
+        let pos = self.events.len() as u32;
+        self.push_event(Event::Start {
+            kind: SyntaxKind(0),
+            forward_parent: None,
+        });
+        Marker::new(pos)
+    }
+
+    /// Consumes the current token. Panics at the end of input.
+    pub fn bump(&mut self) {
+        let kind = self.current().expect("bump at the end of input");
+        self.bump_remap(kind)
+    }
+
+    /// Consumes the current token as a token of kind `kind`, for example to
+    /// turn an identifier into a contextual keyword.
+    pub fn bump_remap(&mut self, kind: SyntaxKind) {
+        self.bump_glued(kind, 1)
+    }
+
+    /// Consumes `n_raw_tokens` joint tokens as a single token of kind `kind`,
+    /// for example `>` `>` as `>>`.
+    pub fn bump_glued(&mut self, kind: SyntaxKind, n_raw_tokens: u8) {
+        assert!(n_raw_tokens > 0);
+        for i in 0..n_raw_tokens as usize {
+            assert!(self.nth(i).is_some(), "bump at the end of input");
+            assert!(
+                i == 0 || self.is_joint(i - 1),
+                "gluing tokens separated by trivia"
+            );
+        }
+        for _ in 0..n_raw_tokens {
+            self.source.bump();
+        }
+        self.push_event(Event::Token { kind, n_raw_tokens });
+    }
+
+    /// Consumes the current token if it is of kind `kind`.
+    pub fn eat(&mut self, kind: SyntaxKind) -> bool {
+        if !self.at(kind) {
+            return false;
+        }
+        self.bump();
+        true
+    }
+
+    /// Records an error at the current position.
+    pub fn error(&mut self, message: impl Into<String>) {
+        self.push_event(Event::Error {
+            message: message.into(),
+        })
+    }
+
+    fn push_event(&mut self, event: Event) {
+        self.events.push(event)
+    }
+}
+
+/// An unfinished node, see [`Parser::start`].
+#[must_use]
+#[derive(Debug)]
+pub struct Marker {
+    pos: u32,
+    done: bool,
+}
+
+impl Marker {
+    fn new(pos: u32) -> Marker {
+        Marker { pos, done: false }
+    }
+
+    /// Finishes the node, giving it `kind`.
+    pub fn complete<S>(mut self, p: &mut Parser<S>, kind: SyntaxKind) -> CompletedMarker {
+        self.done = true;
+        match &mut p.events[self.pos as usize] {
+            Event::Start { kind: slot, .. } => *slot = kind,
+            _ => unreachable!(),
+        }
+        p.events.push(Event::Finish);
+        CompletedMarker {
+            pos: self.pos,
+            kind,
+        }
+    }
+
+    /// Drops the node; its children become children of the enclosing node.
+    pub fn abandon<S>(mut self, p: &mut Parser<S>) {
+        self.done = true;
+        // Not popped even if it is the last event: a completed node may point
+        // to it as its forward parent.
+        p.events[self.pos as usize] = Event::Tombstone;
+    }
+}
+
+impl Drop for Marker {
+    fn drop(&mut self) {
+        if !self.done && !std::thread::panicking() {
+            panic!("marker must be either completed or abandoned")
+        }
+    }
+}
+
+/// A finished node, see [`Marker::complete`].
+#[derive(Debug, Clone, Copy)]
+pub struct CompletedMarker {
+    pos: u32,
+    kind: SyntaxKind,
+}
+
+impl CompletedMarker {
+    pub fn kind(&self) -> SyntaxKind {
+        self.kind
+    }
+
+    /// Starts a new node which will become the parent of this one, even though
+    /// this one has been started first.
+    pub fn precede<S>(self, p: &mut Parser<S>) -> Marker {
+        let new_pos = p.events.len() as u32;
+        p.events.push(Event::Start {
+            kind: SyntaxKind(0),
+            forward_parent: None,
+        });
+        match &mut p.events[self.pos as usize] {
+            Event::Start { forward_parent, .. } => *forward_parent = Some(new_pos - self.pos),
+            _ => unreachable!(),
+        }
+        Marker::new(new_pos)
+    }
+}
+
+/// Replays `events` into `builder`, taking token texts from `text`, which is
+/// split into `tokens`, including trivia.
+///
+/// Trivia never starts or ends a node, except for the root: leading trivia
+/// is placed before a node starts, and trailing trivia after it ends. Errors
+/// get an empty range at the position they were recorded at.
+///
+/// # Panics
+///
+/// Panics if the events don't consume exactly `tokens`, if the lengths of
+/// `tokens` don't add up to `text`, or if the events don't form a single
+/// tree.
+pub fn build_tree<L: Language>(
+    mut events: Vec<Event>,
+    text: &str,
+    tokens: &[(SyntaxKind, TextSize)],
+    builder: GreenNodeBuilder<'_>,
+) -> (GreenNode, Vec<SyntaxError>) {
+    let mut sink = Sink::<L> {
+        text,
+        tokens,
+        _p: PhantomData,
+        pos: 0,
+        offset: 0.into(),
+        depth: 0,
+        builder,
+        errors: Vec::new(),
+    };
+    let mut forward_parents = Vec::new();
+    for i in 0..events.len() {
+        match mem::replace(&mut events[i], Event::Tombstone) {
+            Event::Start {
+                kind,
+                forward_parent,
+            } => {
+                forward_parents.push(kind);
+                let (mut idx, mut forward_parent) = (i, forward_parent);
+                while let Some(distance) = forward_parent {
+                    idx += distance as usize;
+                    forward_parent = match mem::replace(&mut events[idx], Event::Tombstone) {
+                        Event::Start {
+                            kind,
+                            forward_parent,
+                        } => {
+                            forward_parents.push(kind);
+                            forward_parent
+                        }
+                        // An abandoned `precede`.
+                        Event::Tombstone => None,
+                        _ => unreachable!(),
+                    };
+                }
+                for kind in forward_parents.drain(..).rev() {
+                    sink.start_node(kind);
+                }
+            }
+            Event::Finish => sink.finish_node(),
+            Event::Token { kind, n_raw_tokens } => sink.token(kind, n_raw_tokens),
+            Event::Error { message } => sink.error(message),
+            Event::Tombstone => (),
+        }
+    }
+    sink.finish()
+}
+
+struct Sink<'a, 'b, L> {
+    text: &'a str,
+    tokens: &'a [(SyntaxKind, TextSize)],
+    _p: PhantomData<L>,
+    /// Index of the next raw token.
+    pos: usize,
+    offset: TextSize,
+    depth: usize,
+    builder: GreenNodeBuilder<'b>,
+    errors: Vec<SyntaxError>,
+}
+
+impl<L: Language> Sink<'_, '_, L> {
+    fn start_node(&mut self, kind: SyntaxKind) {
+        // Leading trivia of the whole input belongs to the root.
+        if self.depth > 0 {
+            self.eat_trivia();
+        }
+        self.builder.start_node(kind);
+        self.depth += 1;
+    }
+
+    fn finish_node(&mut self) {
+        self.depth -= 1;
+        if self.depth == 0 {
+            self.eat_trivia();
+        }
+        self.builder.finish_node();
+    }
+
+    fn token(&mut self, kind: SyntaxKind, n_raw_tokens: u8) {
+        self.eat_trivia();
+        self.do_token(kind, n_raw_tokens as usize);
+    }
+
+    fn error(&mut self, message: String) {
+        self.errors
+            .push(SyntaxError::new(message, TextRange::empty(self.offset)));
+    }
+
+    fn eat_trivia(&mut self) {
+        while let Some(&(kind, _)) = self.tokens.get(self.pos) {
+            if !is_trivia::<L>(kind) {
+                break;
+            }
+            self.do_token(kind, 1);
+        }
+    }
+
+    fn do_token(&mut self, kind: SyntaxKind, n_raw_tokens: usize) {
+        let raw = &self.tokens[self.pos..self.pos + n_raw_tokens];
+        let len = raw.iter().map(|&(_, len)| len).sum::<TextSize>();
+        let range = TextRange::at(self.offset, len);
+        self.builder.token(kind, &self.text[range]);
+        self.pos += n_raw_tokens;
+        self.offset = range.end();
+    }
+
+    fn finish(self) -> (GreenNode, Vec<SyntaxError>) {
+        assert_eq!(self.pos, self.tokens.len(), "not all tokens were consumed");
+        (self.builder.finish(), self.errors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_utils::{TestLang, WS};
+
+    use super::*;
+
+    const NUM: SyntaxKind = SyntaxKind(1);
+    const OP: SyntaxKind = SyntaxKind(2);
+    const ROOT: SyntaxKind = SyntaxKind(3);
+    const BIN: SyntaxKind = SyntaxKind(4);
+    const PAREN: SyntaxKind = SyntaxKind(5);
+
+    fn lex(text: &str) -> Vec<(SyntaxKind, TextSize)> {
+        let kind = |c: char| match c {
+            ' ' => WS,
+            '0'..='9' => NUM,
+            _ => OP,
+        };
+        text.chars().map(|c| (kind(c), TextSize::of(c))).collect()
+    }
+
+    fn expr<S: TokenSource>(p: &mut Parser<S>) {
+        let m = p.start();
+        p.bump();
+        let mut lhs = m.complete(p, NUM);
+        while p.at(OP) {
+            let m = lhs.precede(p);
+            p.bump();
+            let rhs = p.start();
+            if p.eat(NUM) {
+                rhs.complete(p, NUM);
+            } else {
+                rhs.abandon(p);
+                p.error("expected a number");
+            }
+            lhs = m.complete(p, BIN);
+        }
+    }
+
+    fn parse(text: &str) -> (String, Vec<SyntaxError>) {
+        let tokens = lex(text);
+        let mut p = Parser::new(RawTokens::new::<TestLang>(&tokens));
+        let root = p.start();
+        let paren = p.start();
+        expr(&mut p);
+        // Nodes which turn out not to be needed.
+        paren.abandon(&mut p);
+        let m = p.start();
+        m.abandon(&mut p);
+        root.complete(&mut p, ROOT);
+        let (green, errors) =
+            build_tree::<TestLang>(p.finish(), text, &tokens, GreenNodeBuilder::new());
+        assert_eq!(green.to_string(), text);
+        assert!(!green.children().any(|it| it.kind() == PAREN));
+        (green.dump(|kind| format!("{}", kind.0)), errors)
+    }
+
+    #[test]
+    fn forward_parents_and_trivia() {
+        let (dump, errors) = parse(" 1 + 2+3 ");
+        assert!(errors.is_empty());
+        let expected = r#"3@0..9
+  0@0..1 " "
+  4@1..8
+    4@1..6
+      1@1..2
+        1@1..2 "1"
+      0@2..3 " "
+      2@3..4 "+"
+      0@4..5 " "
+      1@5..6
+        1@5..6 "2"
+    2@6..7 "+"
+    1@7..8
+      1@7..8 "3"
+  0@8..9 " "
+"#;
+        assert_eq!(dump, expected);
+
+        let tokens = lex("1");
+        let mut p = Parser::new(RawTokens::new::<TestLang>(&tokens));
+        let m = p.start();
+        p.bump();
+        let lit = m.complete(&mut p, NUM);
+        lit.precede(&mut p).abandon(&mut p);
+        let (green, _) = build_tree::<TestLang>(p.finish(), "1", &tokens, GreenNodeBuilder::new());
+        assert_eq!(green.kind(), NUM);
+
+        let (_, errors) = parse("1 +");
+        assert_eq!(
+            errors,
+            [SyntaxError::new(
+                "expected a number",
+                TextRange::empty(3.into())
+            )]
+        );
+    }
+}
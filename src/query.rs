@@ -0,0 +1,464 @@
+//! S-expression queries over syntax trees, in the spirit of tree-sitter.
+//!
+//! A query is a list of patterns:
+//!
+//! ```text
+//! (RULE                      ; a node of kind RULE
+//!   (CONDITION               ; ...with a CONDITION child
+//!     (FOR_EXPR @for)        ; ...which has a FOR_EXPR child, captured as `for`
+//!     !ERROR)                ; ...and no ERROR child
+//!   IDENT @name)             ; ...followed by an IDENT node or token
+//! ```
+//!
+//! * `(KIND child...)` matches a node of kind `KIND` whose children match the
+//!   child patterns in order. Other children may appear in between.
+//! * `KIND` matches a node or token of kind `KIND`.
+//! * `(_ child...)` matches any node, `_` any node or token.
+//! * `!pattern` inside a node pattern requires that no child matches.
+//! * `pattern @name` captures the element matched by `pattern`.
+//! * `;` starts a comment running to the end of the line.
+//!
+//! Kinds are referred to by name; [`Query::new`] takes a function resolving
+//! names to `Language::Kind`s.
+
+use std::{collections::VecDeque, error::Error, fmt};
+
+use crate::{api::PreorderWithTokens, Language, SyntaxElement, SyntaxNode, WalkEvent};
+
+/// A compiled query.
+#[derive(Debug, Clone)]
+pub struct Query<L: Language> {
+    patterns: Vec<Pattern<L>>,
+    capture_names: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+struct Pattern<L: Language> {
+    /// `None` for wildcards.
+    kind: Option<L::Kind>,
+    /// Parenthesized patterns match only nodes.
+    node_only: bool,
+    children: Vec<Pattern<L>>,
+    negated: Vec<Pattern<L>>,
+    capture: Option<usize>,
+}
+
+/// An error in the source of a query. Offsets are in bytes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QueryError {
+    UnexpectedEof,
+    UnexpectedChar {
+        offset: usize,
+        char: char,
+    },
+    UnknownKind {
+        offset: usize,
+        name: String,
+    },
+    /// A negation at the top level, or with a capture.
+    InvalidNegation {
+        offset: usize,
+    },
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QueryError::UnexpectedEof => f.write_str("unexpected end of query"),
+            QueryError::UnexpectedChar { offset, char } => {
+                write!(f, "unexpected character {char:?} at {offset}")
+            }
+            QueryError::UnknownKind { offset, name } => {
+                write!(f, "unknown kind `{name}` at {offset}")
+            }
+            QueryError::InvalidNegation { offset } => write!(f, "invalid negation at {offset}"),
+        }
+    }
+}
+
+impl Error for QueryError {}
+
+impl<L: Language> Query<L> {
+    pub fn new<F>(source: &str, resolve: F) -> Result<Query<L>, QueryError>
+    where
+        F: Fn(&str) -> Option<L::Kind>,
+    {
+        let mut parser = QueryParser {
+            source,
+            pos: 0,
+            resolve,
+            capture_names: Vec::new(),
+        };
+        let mut patterns = Vec::new();
+        loop {
+            parser.skip_trivia();
+            if parser.pos == source.len() {
+                break;
+            }
+            if parser.peek() == Some('!') {
+                return Err(QueryError::InvalidNegation { offset: parser.pos });
+            }
+            patterns.push(parser.pattern()?);
+        }
+        Ok(Query {
+            patterns,
+            capture_names: parser.capture_names,
+        })
+    }
+
+    pub fn pattern_count(&self) -> usize {
+        self.patterns.len()
+    }
+
+    /// Names of all captures, indexed by [`QueryCapture::index`].
+    pub fn capture_names(&self) -> &[String] {
+        &self.capture_names
+    }
+
+    pub fn capture_index(&self, name: &str) -> Option<usize> {
+        self.capture_names.iter().position(|it| it == name)
+    }
+}
+
+struct QueryParser<'a, F> {
+    source: &'a str,
+    pos: usize,
+    resolve: F,
+    capture_names: Vec<String>,
+}
+
+impl<'a, F> QueryParser<'a, F> {
+    fn peek(&self) -> Option<char> {
+        self.source[self.pos..].chars().next()
+    }
+
+    fn bump(&mut self) -> Result<char, QueryError> {
+        let c = self.peek().ok_or(QueryError::UnexpectedEof)?;
+        self.pos += c.len_utf8();
+        Ok(c)
+    }
+
+    fn skip_trivia(&mut self) {
+        while let Some(c) = self.peek() {
+            if c == ';' {
+                let rest = &self.source[self.pos..];
+                self.pos += rest.find('\n').unwrap_or(rest.len());
+            } else if c.is_whitespace() {
+                self.pos += c.len_utf8();
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn ident(&mut self) -> Result<&'a str, QueryError> {
+        let start = self.pos;
+        while let Some(c) = self.peek().filter(|&c| c.is_alphanumeric() || c == '_') {
+            self.pos += c.len_utf8();
+        }
+        if start == self.pos {
+            return Err(match self.peek() {
+                Some(char) => QueryError::UnexpectedChar {
+                    offset: self.pos,
+                    char,
+                },
+                None => QueryError::UnexpectedEof,
+            });
+        }
+        Ok(&self.source[start..self.pos])
+    }
+
+    fn kind<L: Language>(&mut self) -> Result<Option<L::Kind>, QueryError>
+    where
+        F: Fn(&str) -> Option<L::Kind>,
+    {
+        let offset = self.pos;
+        let name = self.ident()?;
+        if name == "_" {
+            return Ok(None);
+        }
+        match (self.resolve)(name) {
+            Some(kind) => Ok(Some(kind)),
+            None => Err(QueryError::UnknownKind {
+                offset,
+                name: name.to_string(),
+            }),
+        }
+    }
+
+    fn pattern<L: Language>(&mut self) -> Result<Pattern<L>, QueryError>
+    where
+        F: Fn(&str) -> Option<L::Kind>,
+    {
+        let mut pattern = Pattern {
+            kind: None,
+            node_only: false,
+            children: Vec::new(),
+            negated: Vec::new(),
+            capture: None,
+        };
+        if self.peek() == Some('(') {
+            self.bump()?;
+            self.skip_trivia();
+            pattern.kind = self.kind::<L>()?;
+            pattern.node_only = true;
+            loop {
+                self.skip_trivia();
+                match self.peek() {
+                    Some(')') => {
+                        self.bump()?;
+                        break;
+                    }
+                    Some('!') => {
+                        let offset = self.pos;
+                        self.bump()?;
+                        let negated = self.pattern()?;
+                        if negated.capture.is_some() {
+                            return Err(QueryError::InvalidNegation { offset });
+                        }
+                        pattern.negated.push(negated);
+                    }
+                    Some(_) => pattern.children.push(self.pattern()?),
+                    None => return Err(QueryError::UnexpectedEof),
+                }
+            }
+        } else {
+            pattern.kind = self.kind::<L>()?;
+        }
+
+        self.skip_trivia();
+        if self.peek() == Some('@') {
+            self.bump()?;
+            let name = self.ident()?;
+            let index = match self.capture_names.iter().position(|it| it == name) {
+                Some(it) => it,
+                None => {
+                    self.capture_names.push(name.to_string());
+                    self.capture_names.len() - 1
+                }
+            };
+            pattern.capture = Some(index);
+        }
+        Ok(pattern)
+    }
+}
+
+/// An element captured by a query.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct QueryCapture<L: Language> {
+    /// Index into [`Query::capture_names`].
+    pub index: usize,
+    pub element: SyntaxElement<L>,
+}
+
+/// A successful match of one of the patterns of a query.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct QueryMatch<L: Language> {
+    pub pattern_index: usize,
+    /// Captures in the order of the pattern.
+    pub captures: Vec<QueryCapture<L>>,
+}
+
+impl<L: Language> QueryMatch<L> {
+    /// The first element captured with the given index.
+    pub fn capture(&self, index: usize) -> Option<&SyntaxElement<L>> {
+        self.captures
+            .iter()
+            .find(|it| it.index == index)
+            .map(|it| &it.element)
+    }
+
+    /// All elements captured with the given index.
+    pub fn captures_for(&self, index: usize) -> impl Iterator<Item = &SyntaxElement<L>> {
+        self.captures
+            .iter()
+            .filter(move |it| it.index == index)
+            .map(|it| &it.element)
+    }
+}
+
+/// Runs a query against every element of a subtree, in preorder.
+///
+/// For each element and pattern, at most one match is reported.
+pub struct QueryCursor<'q, L: Language> {
+    query: &'q Query<L>,
+    preorder: PreorderWithTokens<L>,
+    pending: VecDeque<QueryMatch<L>>,
+}
+
+impl<'q, L: Language> QueryCursor<'q, L> {
+    pub fn new(query: &'q Query<L>, node: &SyntaxNode<L>) -> QueryCursor<'q, L> {
+        QueryCursor {
+            query,
+            preorder: node.preorder_with_tokens(),
+            pending: VecDeque::new(),
+        }
+    }
+}
+
+impl<L: Language> Iterator for QueryCursor<'_, L> {
+    type Item = QueryMatch<L>;
+
+    fn next(&mut self) -> Option<QueryMatch<L>> {
+        loop {
+            if let Some(it) = self.pending.pop_front() {
+                return Some(it);
+            }
+            let element = match self.preorder.next()? {
+                WalkEvent::Enter(it) => it,
+                WalkEvent::Leave(_) => continue,
+            };
+            for (pattern_index, pattern) in self.query.patterns.iter().enumerate() {
+                let mut captures = Vec::new();
+                if pattern.matches(&element, &mut captures) {
+                    self.pending.push_back(QueryMatch {
+                        pattern_index,
+                        captures,
+                    });
+                }
+            }
+        }
+    }
+}
+
+impl<L: Language> Pattern<L> {
+    /// On success, appends the captures to `captures`, otherwise leaves it
+    /// unchanged.
+    fn matches(&self, element: &SyntaxElement<L>, captures: &mut Vec<QueryCapture<L>>) -> bool {
+        if self.kind.is_some_and(|kind| kind != element.kind())
+            || (self.node_only && element.as_node().is_none())
+        {
+            return false;
+        }
+        let mark = captures.len();
+        if let Some(index) = self.capture {
+            captures.push(QueryCapture {
+                index,
+                element: element.clone(),
+            });
+        }
+        if let Some(node) = element.as_node().filter(|_| self.node_only) {
+            let children = node.children_with_tokens().collect::<Vec<_>>();
+            let negated = self.negated.iter().any(|pattern| {
+                children
+                    .iter()
+                    .any(|child| pattern.matches(child, &mut Vec::new()))
+            });
+            if negated || !match_sequence(&self.children, &children, captures) {
+                captures.truncate(mark);
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// Matches `patterns` against a subsequence of `elements`, backtracking over
+/// the choice of elements.
+fn match_sequence<L: Language>(
+    patterns: &[Pattern<L>],
+    elements: &[SyntaxElement<L>],
+    captures: &mut Vec<QueryCapture<L>>,
+) -> bool {
+    let (first, rest) = match patterns.split_first() {
+        Some(it) => it,
+        None => return true,
+    };
+    for (i, element) in elements.iter().enumerate() {
+        let mark = captures.len();
+        if first.matches(element, captures) {
+            if match_sequence(rest, &elements[i + 1..], captures) {
+                return true;
+            }
+            captures.truncate(mark);
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{test_utils::TestLang, GreenNodeBuilder, SyntaxKind};
+
+    use super::*;
+
+    const NAMES: [&str; 4] = ["ROOT", "LIST", "ATOM", "ERROR"];
+
+    fn resolve(name: &str) -> Option<SyntaxKind> {
+        NAMES
+            .iter()
+            .position(|&it| it == name)
+            .map(|it| SyntaxKind(it as u16))
+    }
+
+    /// `(a (b) c)` => ROOT(LIST(ATOM LIST(ATOM) ATOM)), `?` is an ERROR token.
+    fn parse(text: &str) -> SyntaxNode<TestLang> {
+        let mut builder = GreenNodeBuilder::new();
+        builder.start_node(SyntaxKind(0));
+        for c in text.chars() {
+            match c {
+                '(' => builder.start_node(SyntaxKind(1)),
+                ')' => builder.finish_node(),
+                '?' => builder.token(SyntaxKind(3), "?"),
+                _ => builder.token(SyntaxKind(2), &c.to_string()),
+            }
+        }
+        builder.finish_node();
+        SyntaxNode::new_root(builder.finish())
+    }
+
+    fn run(query: &str, text: &str) -> Vec<String> {
+        let query = Query::new(query, resolve).unwrap();
+        QueryCursor::new(&query, &parse(text))
+            .map(|m| {
+                let captures = m
+                    .captures
+                    .iter()
+                    .map(|c| format!("{}={}", query.capture_names()[c.index], c.element));
+                format!(
+                    "{}: {}",
+                    m.pattern_index,
+                    captures.collect::<Vec<_>>().join(" ")
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn query_matches() {
+        assert_eq!(run("(LIST ATOM @a)", "(xy(z))"), ["0: a=x", "0: a=z"]);
+        assert_eq!(
+            run("(LIST (LIST) ATOM @a) @l", "(x(y)z)(w)"),
+            ["0: l=xyz a=z"]
+        );
+        assert_eq!(run("(LIST !ERROR) @ok", "(x)(?)"), ["0: ok=x"]);
+        assert_eq!(
+            run("(_ (_) @inner) _ @any", "(x)"),
+            ["0: inner=x", "1: any=x", "1: any=x", "1: any=x"]
+        );
+        assert_eq!(run("; comment\n ERROR @e", "(?)"), ["0: e=?"]);
+        // A node pattern meeting a token doesn't keep its capture.
+        assert_eq!(run("(LIST (_) @n)", "(x(y))"), ["0: n=y"]);
+
+        let err = |query| Query::<TestLang>::new(query, resolve).unwrap_err();
+        assert_eq!(err("(LIST"), QueryError::UnexpectedEof);
+        assert_eq!(
+            err("(FOO)"),
+            QueryError::UnknownKind {
+                offset: 1,
+                name: "FOO".into()
+            }
+        );
+        assert_eq!(
+            err("(LIST !ATOM @a)"),
+            QueryError::InvalidNegation { offset: 6 }
+        );
+        assert_eq!(
+            err("(LIST) @)"),
+            QueryError::UnexpectedChar {
+                offset: 8,
+                char: ')'
+            }
+        );
+    }
+}
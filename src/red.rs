@@ -96,11 +96,11 @@ use std::{
 use countme::Count;
 
 use crate::{
-    green::{GreenChild, GreenElementRef, GreenNodeData, GreenTokenData, SyntaxKind},
+    green::{GreenChild, GreenElementRef, GreenNodeData, GreenTokenData, RelexError, SyntaxKind},
     sll,
     utility_types::Delta,
-    Direction, GreenNode, GreenToken, NodeOrToken, SyntaxText, TextRange, TextSize, TokenAtOffset,
-    WalkEvent,
+    Direction, GreenNode, GreenToken, Indel, NodeOrToken, SyntaxText, TextRange, TextSize,
+    TokenAtOffset, WalkEvent,
 };
 
 enum Green {
@@ -599,6 +599,11 @@ impl SyntaxNode {
         SyntaxNode::new_root(self.green().into())
     }
 
+    #[inline]
+    pub fn is_mutable(&self) -> bool {
+        self.data().mutable
+    }
+
     #[inline]
     fn data(&self) -> &NodeData {
         unsafe { self.ptr.as_ref() }
@@ -803,9 +808,6 @@ impl SyntaxNode {
     }
 
     pub fn token_at_offset(&self, offset: TextSize) -> TokenAtOffset<SyntaxToken> {
-        // TODO: this could be faster if we first drill-down to node, and only
-        // then switch to token search. We should also replace explicit
-        // recursion with a loop.
         let range = self.text_range();
         assert!(
             range.start() <= offset && offset <= range.end(),
@@ -813,30 +815,7 @@ impl SyntaxNode {
             range,
             offset
         );
-        if range.is_empty() {
-            return TokenAtOffset::None;
-        }
-
-        let mut children = self.children_with_tokens().filter(|child| {
-            let child_range = child.text_range();
-            !child_range.is_empty()
-                && (child_range.start() <= offset && offset <= child_range.end())
-        });
-
-        let left = children.next().unwrap();
-        let right = children.next();
-        assert!(children.next().is_none());
-
-        if let Some(right) = right {
-            match (left.token_at_offset(offset), right.token_at_offset(offset)) {
-                (TokenAtOffset::Single(left), TokenAtOffset::Single(right)) => {
-                    TokenAtOffset::Between(left, right)
-                }
-                _ => unreachable!(),
-            }
-        } else {
-            left.token_at_offset(offset)
-        }
+        SyntaxElement::from(self.clone()).token_at_offset(offset)
     }
 
     pub fn covering_element(&self, range: TextRange) -> SyntaxElement {
@@ -872,6 +851,17 @@ impl SyntaxNode {
             })
     }
 
+    pub(crate) fn child_or_token_at_index(&self, index: usize) -> Option<SyntaxElement> {
+        self.green_ref().children().raw.nth(index).map(|child| {
+            SyntaxElement::new(
+                child.as_ref(),
+                self.clone(),
+                index as u32,
+                self.offset() + child.rel_offset(),
+            )
+        })
+    }
+
     pub fn splice_children(&self, to_delete: Range<usize>, to_insert: Vec<SyntaxElement>) {
         assert!(self.data().mutable, "immutable tree: {}", self);
         for (i, child) in self.children_with_tokens().enumerate() {
@@ -916,6 +906,17 @@ impl SyntaxToken {
         }
     }
 
+    /// Creates a mutable token without a parent, ready to be attached to a
+    /// mutable tree with [`SyntaxNode::splice_children`].
+    pub fn new_detached(green: GreenToken) -> SyntaxToken {
+        let green = Green::Token {
+            ptr: GreenToken::into_raw(green),
+        };
+        SyntaxToken {
+            ptr: NodeData::new(None, 0, 0.into(), green, true),
+        }
+    }
+
     #[inline]
     fn data(&self) -> &NodeData {
         unsafe { self.ptr.as_ref() }
@@ -932,6 +933,55 @@ impl SyntaxToken {
         parent.replace_with(new_parent)
     }
 
+    /// Applies `edit` to this token and returns the new root, without
+    /// reparsing anything. The offsets of `edit` are absolute, like
+    /// `text_range`. See [`GreenTokenData::relex`] for the `relex` contract.
+    ///
+    /// Fails if the edited token would merge with the closest non-empty
+    /// token on either side, which is checked by relexing it together with
+    /// the adjacent character and with the whole neighbouring token.
+    pub fn relex<F>(&self, edit: &Indel, mut relex: F) -> Result<GreenNode, RelexError>
+    where
+        F: FnMut(&str) -> Option<SyntaxKind>,
+    {
+        let range = self.text_range();
+        if !range.contains_range(edit.delete) {
+            return Err(RelexError::EditOutsideToken);
+        }
+        let edit = Indel::replace(edit.delete - range.start(), edit.insert.clone());
+        let new_token = self.green().relex(&edit, &mut relex)?;
+
+        // Check that the edited token didn't glue together with a neighbour,
+        // e.g. removing `ruh` from `bruh"str"` turns `b` into a string prefix.
+        let new_text = new_token.text();
+        let next = iter::successors(self.next_token(), |it| it.next_token())
+            .find(|it| !it.text_range().is_empty());
+        if let Some(next) = next {
+            let next = next.text();
+            let first_char = next.chars().next().map_or(0, char::len_utf8);
+            if [&next[..first_char], next]
+                .iter()
+                .any(|it| relex(&format!("{new_text}{it}")).is_some())
+            {
+                return Err(RelexError::MergesWithNext);
+            }
+        }
+        let prev = iter::successors(self.prev_token(), |it| it.prev_token())
+            .find(|it| !it.text_range().is_empty());
+        if let Some(prev) = prev {
+            let prev = prev.text();
+            let last_char = prev.chars().next_back().map_or(0, char::len_utf8);
+            if [&prev[prev.len() - last_char..], prev]
+                .iter()
+                .any(|it| relex(&format!("{it}{new_text}")).is_some())
+            {
+                return Err(RelexError::MergesWithPrevious);
+            }
+        }
+
+        Ok(self.replace_with(new_token))
+    }
+
     #[inline]
     pub fn kind(&self) -> SyntaxKind {
         self.data().kind()
@@ -1015,6 +1065,11 @@ impl SyntaxToken {
         }
     }
 
+    #[inline]
+    pub fn is_mutable(&self) -> bool {
+        self.data().mutable
+    }
+
     pub fn detach(&self) {
         assert!(self.data().mutable, "immutable tree: {}", self);
         self.data().detach()
@@ -1105,11 +1160,48 @@ impl SyntaxElement {
         }
     }
 
+    /// Descends to the tokens at `offset`, only creating the nodes on the
+    /// way there. The children at each level are found by binary search.
     fn token_at_offset(&self, offset: TextSize) -> TokenAtOffset<SyntaxToken> {
-        assert!(self.text_range().start() <= offset && offset <= self.text_range().end());
+        let mut element = self.clone();
+        loop {
+            let node = match element {
+                NodeOrToken::Token(token) => return TokenAtOffset::Single(token),
+                NodeOrToken::Node(node) => node,
+            };
+            let mut children = node
+                .green_ref()
+                .children_at_offset(offset - node.offset())
+                .filter(|(_, child)| child.as_ref().text_len() != 0.into())
+                .map(|(index, _)| index);
+            let left = match children.next() {
+                Some(it) => it,
+                None => return TokenAtOffset::None,
+            };
+            let right = children.next();
+            let left = node.child_or_token_at_index(left).unwrap();
+            element = match right {
+                None => left,
+                Some(right) => {
+                    // The offset is an end of both children, so each of them
+                    // has a single token there.
+                    let right = node.child_or_token_at_index(right).unwrap();
+                    return match (left.token_at_offset(offset), right.token_at_offset(offset)) {
+                        (TokenAtOffset::Single(left), TokenAtOffset::Single(right)) => {
+                            TokenAtOffset::Between(left, right)
+                        }
+                        _ => unreachable!(),
+                    };
+                }
+            };
+        }
+    }
+
+    #[inline]
+    pub fn is_mutable(&self) -> bool {
         match self {
-            NodeOrToken::Token(token) => TokenAtOffset::Single(token.clone()),
-            NodeOrToken::Node(node) => node.token_at_offset(offset),
+            NodeOrToken::Node(it) => it.is_mutable(),
+            NodeOrToken::Token(it) => it.is_mutable(),
         }
     }
 
@@ -1364,3 +1456,72 @@ impl Iterator for PreorderWithTokens {
     }
 }
 // endregion
+
+#[cfg(test)]
+mod tests {
+    use crate::{GreenNodeBuilder, SyntaxKind};
+
+    use super::*;
+
+    #[test]
+    fn splice_children_inserts_into_green() {
+        let mut builder = GreenNodeBuilder::new();
+        builder.start_node(SyntaxKind(0));
+        builder.token(SyntaxKind(1), "a");
+        builder.token(SyntaxKind(1), "b");
+        builder.finish_node();
+        let root = SyntaxNode::new_root_mut(builder.finish());
+
+        let mut builder = GreenNodeBuilder::new();
+        builder.start_node(SyntaxKind(2));
+        builder.token(SyntaxKind(1), "x");
+        builder.finish_node();
+        let new = SyntaxNode::new_root_mut(builder.finish());
+        root.splice_children(1..1, vec![new.into()]);
+        assert_eq!(root.to_string(), "axb");
+        assert_eq!(root.green().children().len(), 3);
+    }
+
+    #[test]
+    fn token_at_offset_with_empty_and_wide_nodes() {
+        // ROOT(WIDE("0" .. "9") EMPTY() "" EMPTY(X()) "ab")
+        let mut builder = GreenNodeBuilder::new();
+        builder.start_node(SyntaxKind(0));
+        builder.start_node(SyntaxKind(1));
+        for digit in 0..10 {
+            builder.token(SyntaxKind(10), &digit.to_string());
+        }
+        builder.finish_node();
+        builder.start_node(SyntaxKind(2));
+        builder.finish_node();
+        builder.token(SyntaxKind(11), "");
+        builder.start_node(SyntaxKind(2));
+        builder.start_node(SyntaxKind(3));
+        builder.finish_node();
+        builder.finish_node();
+        builder.token(SyntaxKind(12), "ab");
+        builder.finish_node();
+        let root = SyntaxNode::new_root(builder.finish());
+
+        let tokens: Vec<_> = root
+            .descendants_with_tokens()
+            .filter_map(|it| it.into_token())
+            .filter(|it| !it.text_range().is_empty())
+            .collect();
+        for offset in 0..=12 {
+            let offset = TextSize::from(offset);
+            let expected = tokens
+                .iter()
+                .filter(|it| it.text_range().contains_inclusive(offset))
+                .cloned();
+            assert!(root.token_at_offset(offset).eq(expected));
+        }
+        assert!(matches!(
+            root.token_at_offset(10.into()),
+            TokenAtOffset::Between(left, right) if left.text() == "9" && right.text() == "ab"
+        ));
+
+        let empty = root.children().nth(1).unwrap();
+        assert!(empty.token_at_offset(10.into()).next().is_none());
+    }
+}
@@ -0,0 +1,311 @@
+//! Serde support for green trees.
+//!
+//! A node is serialized as `{ kind, children }`, where every child is either
+//! a `Node` or a `Token` variant, and a token is serialized as
+//! `{ kind, text }`.
+//!
+//! Deserialization goes through a [`GreenNodeBuilder`], so the resulting tree
+//! is interned in a [`NodeCache`] exactly like a freshly parsed one. Use
+//! [`NodeCache::deserialize_node`] to share the cache between several trees.
+
+use std::fmt;
+
+use serde::{
+    de::{self, DeserializeSeed, EnumAccess, MapAccess, SeqAccess, VariantAccess, Visitor},
+    ser::{SerializeSeq, SerializeStruct},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+
+use crate::{
+    green::Children, Checkpoint, GreenNode, GreenNodeBuilder, GreenNodeData, GreenToken,
+    GreenTokenData, NodeCache, NodeOrToken, SyntaxKind,
+};
+
+const NODE_FIELDS: &[&str] = &["kind", "children"];
+const ELEMENT_VARIANTS: &[&str] = &["Node", "Token"];
+
+impl Serialize for GreenNodeData {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("GreenNode", 2)?;
+        state.serialize_field("kind", &self.kind())?;
+        state.serialize_field("children", &SerDeChildren(self.children()))?;
+        state.end()
+    }
+}
+
+impl Serialize for GreenNode {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let data: &GreenNodeData = self;
+        data.serialize(serializer)
+    }
+}
+
+impl Serialize for GreenTokenData {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("GreenToken", 2)?;
+        state.serialize_field("kind", &self.kind())?;
+        state.serialize_field("text", self.text())?;
+        state.end()
+    }
+}
+
+impl Serialize for GreenToken {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let data: &GreenTokenData = self;
+        data.serialize(serializer)
+    }
+}
+
+struct SerDeChildren<'a>(Children<'a>);
+
+impl Serialize for SerDeChildren<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_seq(Some(self.0.len()))?;
+        for child in self.0.clone() {
+            match child {
+                NodeOrToken::Node(it) => state.serialize_element(&SerDeElement::Node(it))?,
+                NodeOrToken::Token(it) => state.serialize_element(&SerDeElement::Token(it))?,
+            }
+        }
+        state.end()
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename = "GreenElement")]
+enum SerDeElement<'a> {
+    Node(&'a GreenNodeData),
+    Token(&'a GreenTokenData),
+}
+
+#[derive(Deserialize)]
+#[serde(rename = "GreenToken")]
+struct TokenRepr {
+    kind: SyntaxKind,
+    text: String,
+}
+
+impl<'de> Deserialize<'de> for GreenToken {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let TokenRepr { kind, text } = TokenRepr::deserialize(deserializer)?;
+        Ok(GreenToken::new(kind, &text))
+    }
+}
+
+impl<'de> Deserialize<'de> for GreenNode {
+    /// Deserializes a tree with a fresh [`NodeCache`], so identical subtrees
+    /// within the tree are shared.
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        NodeCache::default().deserialize_node(deserializer)
+    }
+}
+
+impl NodeCache {
+    /// Deserializes a green tree, interning all of its nodes and tokens in
+    /// this cache. Trees deserialized with the same cache share their
+    /// identical subtrees, just like trees built with the same
+    /// [`GreenNodeBuilder`] cache.
+    pub fn deserialize_node<'de, D: Deserializer<'de>>(
+        &mut self,
+        deserializer: D,
+    ) -> Result<GreenNode, D::Error> {
+        let mut builder = GreenNodeBuilder::with_cache(self);
+        NodeSeed {
+            builder: &mut builder,
+        }
+        .deserialize(deserializer)?;
+        Ok(builder.finish())
+    }
+}
+
+/// Deserializes a node into the builder. Children are pushed first and wrapped
+/// with `start_node_at`, so the order of `kind` and `children` in the input
+/// doesn't matter.
+struct NodeSeed<'a, 'cache> {
+    builder: &'a mut GreenNodeBuilder<'cache>,
+}
+
+impl<'de> DeserializeSeed<'de> for NodeSeed<'_, '_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_struct("GreenNode", NODE_FIELDS, self)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(field_identifier, rename_all = "lowercase")]
+enum NodeField {
+    Kind,
+    Children,
+}
+
+impl NodeSeed<'_, '_> {
+    fn finish_node(self, kind: SyntaxKind, checkpoint: Checkpoint) {
+        self.builder.start_node_at(checkpoint, kind);
+        self.builder.finish_node();
+    }
+}
+
+impl<'de> Visitor<'de> for NodeSeed<'_, '_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a green node")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        let checkpoint = self.builder.checkpoint();
+        let kind: SyntaxKind = seq
+            .next_element()?
+            .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        seq.next_element_seed(ChildrenSeed {
+            builder: &mut *self.builder,
+        })?
+        .ok_or_else(|| de::Error::invalid_length(1, &self))?;
+        self.finish_node(kind, checkpoint);
+        Ok(())
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        let checkpoint = self.builder.checkpoint();
+        let mut kind = None;
+        let mut has_children = false;
+        while let Some(field) = map.next_key()? {
+            match field {
+                NodeField::Kind => {
+                    if kind.is_some() {
+                        return Err(de::Error::duplicate_field("kind"));
+                    }
+                    kind = Some(map.next_value()?);
+                }
+                NodeField::Children => {
+                    if has_children {
+                        return Err(de::Error::duplicate_field("children"));
+                    }
+                    map.next_value_seed(ChildrenSeed {
+                        builder: &mut *self.builder,
+                    })?;
+                    has_children = true;
+                }
+            }
+        }
+        let kind = kind.ok_or_else(|| de::Error::missing_field("kind"))?;
+        if !has_children {
+            return Err(de::Error::missing_field("children"));
+        }
+        self.finish_node(kind, checkpoint);
+        Ok(())
+    }
+}
+
+struct ChildrenSeed<'a, 'cache> {
+    builder: &'a mut GreenNodeBuilder<'cache>,
+}
+
+impl<'de> DeserializeSeed<'de> for ChildrenSeed<'_, '_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de> Visitor<'de> for ChildrenSeed<'_, '_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a sequence of green elements")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        while seq
+            .next_element_seed(ElementSeed {
+                builder: &mut *self.builder,
+            })?
+            .is_some()
+        {}
+        Ok(())
+    }
+}
+
+struct ElementSeed<'a, 'cache> {
+    builder: &'a mut GreenNodeBuilder<'cache>,
+}
+
+#[derive(Deserialize)]
+#[serde(variant_identifier)]
+enum ElementVariant {
+    Node,
+    Token,
+}
+
+impl<'de> DeserializeSeed<'de> for ElementSeed<'_, '_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_enum("GreenElement", ELEMENT_VARIANTS, self)
+    }
+}
+
+impl<'de> Visitor<'de> for ElementSeed<'_, '_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a green node or a green token")
+    }
+
+    fn visit_enum<A: EnumAccess<'de>>(self, data: A) -> Result<(), A::Error> {
+        match data.variant()? {
+            (ElementVariant::Node, variant) => variant.newtype_variant_seed(NodeSeed {
+                builder: self.builder,
+            }),
+            (ElementVariant::Token, variant) => {
+                let TokenRepr { kind, text } = variant.newtype_variant()?;
+                self.builder.token(kind, &text);
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build_tree() -> GreenNode {
+        let mut builder = GreenNodeBuilder::new();
+        builder.start_node(SyntaxKind(0));
+        for _ in 0..2 {
+            builder.start_node(SyntaxKind(1));
+            builder.token(SyntaxKind(2), "rule");
+            builder.token(SyntaxKind(3), " ");
+            builder.finish_node();
+        }
+        builder.token(SyntaxKind(4), "{}");
+        builder.finish_node();
+        builder.finish()
+    }
+
+    #[test]
+    fn serde_roundtrip_restores_sharing() {
+        let tree = build_tree();
+        let json = serde_json::to_string(&tree).unwrap();
+        let restored: GreenNode = serde_json::from_str(&json).unwrap();
+        assert_eq!(tree, restored);
+        assert_eq!(tree.to_string(), restored.to_string());
+
+        let mut children = restored.children();
+        let first = children.next().unwrap().into_node().unwrap();
+        let second = children.next().unwrap().into_node().unwrap();
+        assert!(std::ptr::eq(first, second));
+    }
+
+    #[test]
+    fn serde_fields_in_any_order() {
+        let json = r#"{"children":[{"Token":{"text":"a","kind":2}}],"kind":0}"#;
+        let node: GreenNode = serde_json::from_str(json).unwrap();
+        assert_eq!(node.kind(), SyntaxKind(0));
+        assert_eq!(node.to_string(), "a");
+    }
+}
@@ -0,0 +1,1162 @@
+//! Thread-safe snapshots of syntax trees.
+//!
+//! The cursors in [`crate::red`] use `Cell` reference counts and raw parent
+//! pointers, so they can't leave the thread which created them. A snapshot is
+//! a green element with its offset, index and the snapshot of its parent,
+//! shared through `Arc`s, which makes it `Send + Sync`.
+//!
+//! Snapshots are immutable and offer the navigation API of
+//! [`crate::SyntaxNode`], walking the green children directly, so a step
+//! costs the same as with a cursor. [`SyntaxNode::text`] and `Debug` delegate
+//! to [`SyntaxNode::cursor`], which materializes the element in a fresh
+//! immutable tree on the current thread. [`SyntaxNode::cursor_in`] instead
+//! finds the element in an existing tree, keeping the identity and the
+//! mutability of its cursors.
+
+use std::{
+    fmt,
+    hash::{Hash, Hasher},
+    iter,
+    marker::PhantomData,
+    ptr,
+    sync::Arc,
+};
+
+use crate::{
+    api, red, Direction, GreenNode, GreenNodeData, GreenToken, GreenTokenData, Language,
+    NodeOrToken, SyntaxText, TextRange, TextSize, TokenAtOffset, WalkEvent,
+};
+
+type GreenElement = NodeOrToken<GreenNode, GreenToken>;
+
+/// An element of a green tree, together with the way to it from the root.
+#[derive(Clone)]
+struct Position(Arc<PositionData>);
+
+struct PositionData {
+    parent: Option<Position>,
+    index: u32,
+    offset: TextSize,
+    green: GreenElement,
+}
+
+impl Position {
+    fn new_root(green: GreenElement) -> Position {
+        Position(Arc::new(PositionData {
+            parent: None,
+            index: 0,
+            offset: 0.into(),
+            green,
+        }))
+    }
+
+    /// Takes a snapshot of the element. An element without a parent becomes
+    /// the root of its snapshot, even if it is a token.
+    fn of(element: red::SyntaxElement) -> Position {
+        let mut path = Vec::new();
+        let mut node = match element {
+            NodeOrToken::Node(it) => it,
+            NodeOrToken::Token(it) => match it.parent() {
+                Some(parent) => {
+                    path.push(it.index());
+                    parent
+                }
+                None => return Position::new_root(it.green().to_owned().into()),
+            },
+        };
+        while let Some(parent) = node.parent() {
+            path.push(node.index());
+            node = parent;
+        }
+        let mut res = Position::new_root(node.green().into());
+        for &index in path.iter().rev() {
+            res = res.child(index).unwrap();
+        }
+        res
+    }
+
+    fn parent(&self) -> Option<&Position> {
+        self.0.parent.as_ref()
+    }
+
+    fn index(&self) -> usize {
+        self.0.index as usize
+    }
+
+    fn green(&self) -> &GreenElement {
+        &self.0.green
+    }
+
+    fn is_node(&self) -> bool {
+        self.green().as_deref().into_node().is_some()
+    }
+
+    fn text_range(&self) -> TextRange {
+        TextRange::at(self.0.offset, self.green().text_len())
+    }
+
+    fn green_ptr(&self) -> *const () {
+        match self.green() {
+            NodeOrToken::Node(it) => ptr::addr_of!(**it).cast(),
+            NodeOrToken::Token(it) => ptr::addr_of!(**it).cast(),
+        }
+    }
+
+    fn child(&self, index: usize) -> Option<Position> {
+        let node = self.green().as_deref().into_node()?;
+        let child = node.children().raw.as_slice().get(index)?;
+        Some(Position(Arc::new(PositionData {
+            parent: Some(self.clone()),
+            index: index as u32,
+            offset: self.0.offset + child.rel_offset(),
+            green: child.as_ref().to_owned(),
+        })))
+    }
+
+    fn children(&self) -> Children {
+        Children {
+            parent: self.clone(),
+            next: 0,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.green()
+            .as_deref()
+            .into_node()
+            .map_or(0, |it| it.children().len())
+    }
+
+    fn first_child(&self) -> Option<Position> {
+        self.children().find(Position::is_node)
+    }
+    fn last_child(&self) -> Option<Position> {
+        (0..self.len())
+            .rev()
+            .filter_map(|index| self.child(index))
+            .find(Position::is_node)
+    }
+
+    fn next_sibling_or_token(&self) -> Option<Position> {
+        self.parent()?.child(self.index() + 1)
+    }
+    fn prev_sibling_or_token(&self) -> Option<Position> {
+        self.parent()?.child(self.index().checked_sub(1)?)
+    }
+
+    fn next_sibling(&self) -> Option<Position> {
+        let mut siblings = self.parent()?.children();
+        siblings.next = self.index() + 1;
+        siblings.find(Position::is_node)
+    }
+    fn prev_sibling(&self) -> Option<Position> {
+        let parent = self.parent()?;
+        (0..self.index())
+            .rev()
+            .filter_map(|index| parent.child(index))
+            .find(Position::is_node)
+    }
+
+    fn first_token(&self) -> Option<Position> {
+        let mut res = self.clone();
+        while res.is_node() {
+            res = res.child(0)?;
+        }
+        Some(res)
+    }
+    fn last_token(&self) -> Option<Position> {
+        let mut res = self.clone();
+        while res.is_node() {
+            res = res.child(res.len().checked_sub(1)?)?;
+        }
+        Some(res)
+    }
+
+    fn next_token(&self) -> Option<Position> {
+        iter::successors(Some(self), |it| it.parent())
+            .find_map(Position::next_sibling_or_token)?
+            .first_token()
+    }
+    fn prev_token(&self) -> Option<Position> {
+        iter::successors(Some(self), |it| it.parent())
+            .find_map(Position::prev_sibling_or_token)?
+            .last_token()
+    }
+
+    fn token_at_offset(&self, offset: TextSize) -> TokenAtOffset<Position> {
+        let mut res = self.clone();
+        loop {
+            let node = match res.green().as_deref().into_node() {
+                Some(it) => it,
+                None => return TokenAtOffset::Single(res),
+            };
+            let mut children = node
+                .children_at_offset(offset - res.0.offset)
+                .filter(|(_, child)| child.as_ref().text_len() != 0.into())
+                .map(|(index, _)| index);
+            let (left, right) = match children.next() {
+                Some(it) => (it, children.next()),
+                None => return TokenAtOffset::None,
+            };
+            drop(children);
+            let left = res.child(left).unwrap();
+            res = match right {
+                None => left,
+                Some(right) => {
+                    // The offset is an end of both children, so each of them
+                    // has a single token there.
+                    let right = res.child(right).unwrap();
+                    return match (left.token_at_offset(offset), right.token_at_offset(offset)) {
+                        (TokenAtOffset::Single(left), TokenAtOffset::Single(right)) => {
+                            TokenAtOffset::Between(left, right)
+                        }
+                        _ => unreachable!(),
+                    };
+                }
+            };
+        }
+    }
+
+    fn covering_element(&self, range: TextRange) -> Position {
+        let mut res = self.clone();
+        loop {
+            assert!(
+                res.text_range().contains_range(range),
+                "Bad range: node range {:?}, range {:?}",
+                res.text_range(),
+                range,
+            );
+            res = match res.child_or_token_at_range(range) {
+                Some(it) => it,
+                None => return res,
+            };
+        }
+    }
+
+    fn child_or_token_at_range(&self, range: TextRange) -> Option<Position> {
+        let node = self.green().as_deref().into_node()?;
+        let (index, _, _) = node.child_at_range(range - self.0.offset)?;
+        self.child(index)
+    }
+
+    fn replace_with(&self, replacement: GreenElement) -> GreenNode {
+        assert_eq!(self.green().kind(), replacement.kind());
+        let mut res = replacement;
+        let mut pos = self;
+        while let Some(parent) = pos.parent() {
+            let node = parent.green().as_deref().into_node().unwrap();
+            res = node.replace_child(pos.index(), res).into();
+            pos = parent;
+        }
+        res.into_node()
+            .expect("the root of the snapshot is a token")
+    }
+
+    /// The root of the tree and the child indices leading from it to the
+    /// element.
+    fn path(&self) -> (&GreenElement, Vec<usize>) {
+        let mut path = Vec::new();
+        let mut pos = self;
+        while let Some(parent) = pos.parent() {
+            path.push(pos.index());
+            pos = parent;
+        }
+        path.reverse();
+        (pos.green(), path)
+    }
+
+    fn to_red(&self, root: &red::SyntaxNode) -> Option<red::SyntaxElement> {
+        let (green, path) = self.path();
+        let green = green.as_deref().into_node()?;
+        if root.parent().is_some() || !ptr::eq(&*root.green(), green) {
+            return None;
+        }
+        let mut res: red::SyntaxElement = root.clone().into();
+        for index in path {
+            res = res.into_node()?.child_or_token_at_index(index)?;
+        }
+        Some(res)
+    }
+
+    fn cursor(&self) -> red::SyntaxElement {
+        match self.path().0 {
+            NodeOrToken::Node(root) => self
+                .to_red(&red::SyntaxNode::new_root(root.clone()))
+                .unwrap(),
+            NodeOrToken::Token(root) => red::SyntaxToken::new_detached(root.clone()).into(),
+        }
+    }
+}
+
+// Snapshots of long paths are usually dropped leaf first, don't recurse.
+impl Drop for PositionData {
+    fn drop(&mut self) {
+        let mut parent = self.parent.take();
+        while let Some(Position(it)) = parent {
+            parent = Arc::into_inner(it).and_then(|mut it| it.parent.take());
+        }
+    }
+}
+
+// Identity semantics, like the other cursors: equal positions are at the same
+// path in the same root.
+impl PartialEq for Position {
+    fn eq(&self, other: &Position) -> bool {
+        let (mut this, mut other) = (self, other);
+        loop {
+            if Arc::ptr_eq(&this.0, &other.0) {
+                return true;
+            }
+            if this.0.index != other.0.index
+                || this.0.offset != other.0.offset
+                || this.green_ptr() != other.green_ptr()
+            {
+                return false;
+            }
+            match (this.parent(), other.parent()) {
+                (Some(this_parent), Some(other_parent)) => {
+                    this = this_parent;
+                    other = other_parent;
+                }
+                (None, None) => return true,
+                _ => return false,
+            }
+        }
+    }
+}
+
+impl Eq for Position {}
+
+impl Hash for Position {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        ptr::hash(self.green_ptr(), state);
+        self.0.offset.hash(state);
+        self.0.index.hash(state);
+    }
+}
+
+impl fmt::Debug for Position {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}@{:?}", self.green().kind(), self.text_range())
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Children {
+    parent: Position,
+    next: usize,
+}
+
+impl Iterator for Children {
+    type Item = Position;
+    fn next(&mut self) -> Option<Position> {
+        let res = self.parent.child(self.next)?;
+        self.next += 1;
+        Some(res)
+    }
+}
+
+/// Preorder over positions, bounded by `start`, optionally with tokens.
+#[derive(Debug, Clone)]
+struct Walk {
+    start: Position,
+    next: Option<WalkEvent<Position>>,
+    skip_subtree: bool,
+    tokens: bool,
+}
+
+impl Walk {
+    fn new(start: Position, tokens: bool) -> Walk {
+        Walk {
+            next: Some(WalkEvent::Enter(start.clone())),
+            start,
+            skip_subtree: false,
+            tokens,
+        }
+    }
+
+    fn do_skip(&mut self) {
+        self.next = self.next.take().map(|next| match next {
+            WalkEvent::Enter(first_child) => {
+                WalkEvent::Leave(first_child.parent().unwrap().clone())
+            }
+            WalkEvent::Leave(parent) => WalkEvent::Leave(parent),
+        })
+    }
+}
+
+impl Iterator for Walk {
+    type Item = WalkEvent<Position>;
+
+    fn next(&mut self) -> Option<WalkEvent<Position>> {
+        if self.skip_subtree {
+            self.do_skip();
+            self.skip_subtree = false;
+        }
+        let next = self.next.take();
+        self.next = next.as_ref().and_then(|next| {
+            Some(match next {
+                WalkEvent::Enter(pos) => {
+                    let child = if self.tokens {
+                        pos.child(0)
+                    } else {
+                        pos.first_child()
+                    };
+                    match child {
+                        Some(child) => WalkEvent::Enter(child),
+                        None => WalkEvent::Leave(pos.clone()),
+                    }
+                }
+                // The walk never leaves the subtree, so `start` is an ancestor
+                // of every position in it, shared by all of them.
+                WalkEvent::Leave(pos) if Arc::ptr_eq(&pos.0, &self.start.0) => return None,
+                WalkEvent::Leave(pos) => {
+                    let sibling = if self.tokens {
+                        pos.next_sibling_or_token()
+                    } else {
+                        pos.next_sibling()
+                    };
+                    match sibling {
+                        Some(sibling) => WalkEvent::Enter(sibling),
+                        None => WalkEvent::Leave(pos.parent()?.clone()),
+                    }
+                }
+            })
+        });
+        next
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct SyntaxNode<L: Language> {
+    pos: Position,
+    _p: PhantomData<fn() -> L>,
+}
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct SyntaxToken<L: Language> {
+    pos: Position,
+    _p: PhantomData<fn() -> L>,
+}
+
+pub type SyntaxElement<L> = NodeOrToken<SyntaxNode<L>, SyntaxToken<L>>;
+
+impl<L: Language> SyntaxNode<L> {
+    fn from_pos(pos: Position) -> SyntaxNode<L> {
+        SyntaxNode {
+            pos,
+            _p: PhantomData,
+        }
+    }
+
+    pub fn new_root(green: GreenNode) -> SyntaxNode<L> {
+        SyntaxNode::from_pos(Position::new_root(green.into()))
+    }
+
+    /// Materializes this node in a new immutable tree on the current thread.
+    pub fn cursor(&self) -> api::SyntaxNode<L> {
+        self.pos.cursor().into_node().unwrap().into()
+    }
+
+    /// Finds this node in the tree of `root`. Returns `None` if `root` is not
+    /// the root of the tree the snapshot was taken from, e.g. because the tree
+    /// was edited since.
+    pub fn cursor_in(&self, root: &api::SyntaxNode<L>) -> Option<api::SyntaxNode<L>> {
+        let root = red::SyntaxNode::from(root.clone());
+        Some(self.pos.to_red(&root)?.into_node()?.into())
+    }
+
+    /// Returns a green tree, equal to the green tree this node
+    /// belongs to, except with this node substituted. The complexity
+    /// of operation is proportional to the depth of the tree
+    pub fn replace_with(&self, replacement: GreenNode) -> GreenNode {
+        self.pos.replace_with(replacement.into())
+    }
+
+    pub fn kind(&self) -> L::Kind {
+        L::kind_from_raw(self.green().kind())
+    }
+
+    pub fn text_range(&self) -> TextRange {
+        self.pos.text_range()
+    }
+
+    pub fn index(&self) -> usize {
+        self.pos.index()
+    }
+
+    pub fn text(&self) -> SyntaxText {
+        self.cursor().text()
+    }
+
+    pub fn green(&self) -> &GreenNodeData {
+        self.pos.green().as_deref().into_node().unwrap()
+    }
+
+    pub fn parent(&self) -> Option<SyntaxNode<L>> {
+        self.pos.parent().cloned().map(SyntaxNode::from_pos)
+    }
+
+    pub fn ancestors(&self) -> impl Iterator<Item = SyntaxNode<L>> {
+        iter::successors(Some(self.clone()), SyntaxNode::parent)
+    }
+
+    pub fn children(&self) -> SyntaxNodeChildren<L> {
+        SyntaxNodeChildren {
+            raw: self.pos.children(),
+            _p: PhantomData,
+        }
+    }
+
+    pub fn children_with_tokens(&self) -> SyntaxElementChildren<L> {
+        SyntaxElementChildren {
+            raw: self.pos.children(),
+            _p: PhantomData,
+        }
+    }
+
+    pub fn first_child(&self) -> Option<SyntaxNode<L>> {
+        self.pos.first_child().map(SyntaxNode::from_pos)
+    }
+    pub fn last_child(&self) -> Option<SyntaxNode<L>> {
+        self.pos.last_child().map(SyntaxNode::from_pos)
+    }
+
+    pub fn first_child_or_token(&self) -> Option<SyntaxElement<L>> {
+        self.pos.child(0).map(SyntaxElement::from_pos)
+    }
+    pub fn last_child_or_token(&self) -> Option<SyntaxElement<L>> {
+        let index = self.pos.len().checked_sub(1)?;
+        self.pos.child(index).map(SyntaxElement::from_pos)
+    }
+
+    pub fn next_sibling(&self) -> Option<SyntaxNode<L>> {
+        self.pos.next_sibling().map(SyntaxNode::from_pos)
+    }
+    pub fn prev_sibling(&self) -> Option<SyntaxNode<L>> {
+        self.pos.prev_sibling().map(SyntaxNode::from_pos)
+    }
+
+    pub fn next_sibling_or_token(&self) -> Option<SyntaxElement<L>> {
+        self.pos
+            .next_sibling_or_token()
+            .map(SyntaxElement::from_pos)
+    }
+    pub fn prev_sibling_or_token(&self) -> Option<SyntaxElement<L>> {
+        self.pos
+            .prev_sibling_or_token()
+            .map(SyntaxElement::from_pos)
+    }
+
+    /// Return the leftmost token in the subtree of this node.
+    pub fn first_token(&self) -> Option<SyntaxToken<L>> {
+        self.pos.first_token().map(SyntaxToken::from_pos)
+    }
+    /// Return the rightmost token in the subtree of this node.
+    pub fn last_token(&self) -> Option<SyntaxToken<L>> {
+        self.pos.last_token().map(SyntaxToken::from_pos)
+    }
+
+    pub fn siblings(&self, direction: Direction) -> impl Iterator<Item = SyntaxNode<L>> {
+        iter::successors(Some(self.clone()), move |node| match direction {
+            Direction::Next => node.next_sibling(),
+            Direction::Prev => node.prev_sibling(),
+        })
+    }
+
+    pub fn siblings_with_tokens(
+        &self,
+        direction: Direction,
+    ) -> impl Iterator<Item = SyntaxElement<L>> {
+        let me: SyntaxElement<L> = self.clone().into();
+        iter::successors(Some(me), move |el| match direction {
+            Direction::Next => el.next_sibling_or_token(),
+            Direction::Prev => el.prev_sibling_or_token(),
+        })
+    }
+
+    pub fn descendants(&self) -> impl Iterator<Item = SyntaxNode<L>> {
+        self.preorder().filter_map(|event| match event {
+            WalkEvent::Enter(node) => Some(node),
+            WalkEvent::Leave(_) => None,
+        })
+    }
+
+    pub fn descendants_with_tokens(&self) -> impl Iterator<Item = SyntaxElement<L>> {
+        self.preorder_with_tokens().filter_map(|event| match event {
+            WalkEvent::Enter(it) => Some(it),
+            WalkEvent::Leave(_) => None,
+        })
+    }
+
+    /// Traverse the subtree rooted at the current node (including the current
+    /// node) in preorder, excluding tokens.
+    pub fn preorder(&self) -> Preorder<L> {
+        Preorder {
+            raw: Walk::new(self.pos.clone(), false),
+            _p: PhantomData,
+        }
+    }
+
+    /// Traverse the subtree rooted at the current node (including the current
+    /// node) in preorder, including tokens.
+    pub fn preorder_with_tokens(&self) -> PreorderWithTokens<L> {
+        PreorderWithTokens {
+            raw: Walk::new(self.pos.clone(), true),
+            _p: PhantomData,
+        }
+    }
+
+    /// Find a token in the subtree corresponding to this node, which covers the offset.
+    /// Precondition: offset must be withing node's range.
+    pub fn token_at_offset(&self, offset: TextSize) -> TokenAtOffset<SyntaxToken<L>> {
+        self.pos.token_at_offset(offset).map(SyntaxToken::from_pos)
+    }
+
+    /// Return the deepest node or token in the current subtree that fully
+    /// contains the range. If the range is empty and is contained in two leaf
+    /// nodes, either one can be returned. Precondition: range must be contained
+    /// withing the current node
+    pub fn covering_element(&self, range: TextRange) -> SyntaxElement<L> {
+        SyntaxElement::from_pos(self.pos.covering_element(range))
+    }
+
+    /// Finds a [`SyntaxElement`] which intersects with a given `range`. If
+    /// there are several intersecting elements, any one can be returned.
+    pub fn child_or_token_at_range(&self, range: TextRange) -> Option<SyntaxElement<L>> {
+        self.pos
+            .child_or_token_at_range(range)
+            .map(SyntaxElement::from_pos)
+    }
+
+    /// Returns an independent copy of the subtree rooted at this node.
+    ///
+    /// The parent of the returned node will be `None`, the start offset will be
+    /// zero, but, otherwise, it'll be equivalent to the source node.
+    pub fn clone_subtree(&self) -> SyntaxNode<L> {
+        SyntaxNode::new_root(self.green().to_owned())
+    }
+}
+
+impl<L: Language> SyntaxToken<L> {
+    fn from_pos(pos: Position) -> SyntaxToken<L> {
+        SyntaxToken {
+            pos,
+            _p: PhantomData,
+        }
+    }
+
+    /// Materializes this token in a new immutable tree on the current thread.
+    /// A token without a parent is materialized as a detached token.
+    pub fn cursor(&self) -> api::SyntaxToken<L> {
+        self.pos.cursor().into_token().unwrap().into()
+    }
+
+    /// Finds this token in the tree of `root`, see [`SyntaxNode::cursor_in`].
+    /// Always `None` for a token without a parent.
+    pub fn cursor_in(&self, root: &api::SyntaxNode<L>) -> Option<api::SyntaxToken<L>> {
+        let root = red::SyntaxNode::from(root.clone());
+        Some(self.pos.to_red(&root)?.into_token()?.into())
+    }
+
+    /// Returns a green tree, equal to the green tree this token
+    /// belongs to, except with this token substituted. The complexity
+    /// of operation is proportional to the depth of the tree
+    ///
+    /// # Panics
+    ///
+    /// Panics if the token has no parent.
+    pub fn replace_with(&self, replacement: GreenToken) -> GreenNode {
+        self.pos.replace_with(replacement.into())
+    }
+
+    pub fn kind(&self) -> L::Kind {
+        L::kind_from_raw(self.green().kind())
+    }
+
+    pub fn text_range(&self) -> TextRange {
+        self.pos.text_range()
+    }
+
+    pub fn index(&self) -> usize {
+        self.pos.index()
+    }
+
+    pub fn text(&self) -> &str {
+        self.green().text()
+    }
+
+    pub fn green(&self) -> &GreenTokenData {
+        self.pos.green().as_deref().into_token().unwrap()
+    }
+
+    pub fn parent(&self) -> Option<SyntaxNode<L>> {
+        self.pos.parent().cloned().map(SyntaxNode::from_pos)
+    }
+
+    /// Iterator over all the ancestors of this token, starting with its
+    /// parent.
+    pub fn ancestors(&self) -> impl Iterator<Item = SyntaxNode<L>> {
+        self.parent_ancestors()
+    }
+
+    /// Iterator over all the ancestors of this token excluding itself.
+    pub fn parent_ancestors(&self) -> impl Iterator<Item = SyntaxNode<L>> {
+        iter::successors(self.parent(), SyntaxNode::parent)
+    }
+
+    pub fn next_sibling_or_token(&self) -> Option<SyntaxElement<L>> {
+        self.pos
+            .next_sibling_or_token()
+            .map(SyntaxElement::from_pos)
+    }
+    pub fn prev_sibling_or_token(&self) -> Option<SyntaxElement<L>> {
+        self.pos
+            .prev_sibling_or_token()
+            .map(SyntaxElement::from_pos)
+    }
+
+    pub fn siblings_with_tokens(
+        &self,
+        direction: Direction,
+    ) -> impl Iterator<Item = SyntaxElement<L>> {
+        let me: SyntaxElement<L> = self.clone().into();
+        iter::successors(Some(me), move |el| match direction {
+            Direction::Next => el.next_sibling_or_token(),
+            Direction::Prev => el.prev_sibling_or_token(),
+        })
+    }
+
+    /// Next token in the tree (i.e, not necessary a sibling).
+    pub fn next_token(&self) -> Option<SyntaxToken<L>> {
+        self.pos.next_token().map(SyntaxToken::from_pos)
+    }
+    /// Previous token in the tree (i.e, not necessary a sibling).
+    pub fn prev_token(&self) -> Option<SyntaxToken<L>> {
+        self.pos.prev_token().map(SyntaxToken::from_pos)
+    }
+}
+
+impl<L: Language> SyntaxElement<L> {
+    fn from_pos(pos: Position) -> SyntaxElement<L> {
+        if pos.is_node() {
+            NodeOrToken::Node(SyntaxNode::from_pos(pos))
+        } else {
+            NodeOrToken::Token(SyntaxToken::from_pos(pos))
+        }
+    }
+
+    pub fn text_range(&self) -> TextRange {
+        match self {
+            NodeOrToken::Node(it) => it.text_range(),
+            NodeOrToken::Token(it) => it.text_range(),
+        }
+    }
+
+    pub fn index(&self) -> usize {
+        match self {
+            NodeOrToken::Node(it) => it.index(),
+            NodeOrToken::Token(it) => it.index(),
+        }
+    }
+
+    pub fn kind(&self) -> L::Kind {
+        match self {
+            NodeOrToken::Node(it) => it.kind(),
+            NodeOrToken::Token(it) => it.kind(),
+        }
+    }
+
+    pub fn parent(&self) -> Option<SyntaxNode<L>> {
+        match self {
+            NodeOrToken::Node(it) => it.parent(),
+            NodeOrToken::Token(it) => it.parent(),
+        }
+    }
+
+    pub fn ancestors(&self) -> impl Iterator<Item = SyntaxNode<L>> {
+        let first = match self {
+            NodeOrToken::Node(it) => Some(it.clone()),
+            NodeOrToken::Token(it) => it.parent(),
+        };
+        iter::successors(first, SyntaxNode::parent)
+    }
+
+    pub fn first_token(&self) -> Option<SyntaxToken<L>> {
+        match self {
+            NodeOrToken::Node(it) => it.first_token(),
+            NodeOrToken::Token(it) => Some(it.clone()),
+        }
+    }
+    pub fn last_token(&self) -> Option<SyntaxToken<L>> {
+        match self {
+            NodeOrToken::Node(it) => it.last_token(),
+            NodeOrToken::Token(it) => Some(it.clone()),
+        }
+    }
+
+    pub fn next_sibling_or_token(&self) -> Option<SyntaxElement<L>> {
+        match self {
+            NodeOrToken::Node(it) => it.next_sibling_or_token(),
+            NodeOrToken::Token(it) => it.next_sibling_or_token(),
+        }
+    }
+    pub fn prev_sibling_or_token(&self) -> Option<SyntaxElement<L>> {
+        match self {
+            NodeOrToken::Node(it) => it.prev_sibling_or_token(),
+            NodeOrToken::Token(it) => it.prev_sibling_or_token(),
+        }
+    }
+}
+
+// region: impls
+
+impl<L: Language> fmt::Debug for SyntaxNode<L> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.cursor(), f)
+    }
+}
+
+impl<L: Language> fmt::Display for SyntaxNode<L> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self.green(), f)
+    }
+}
+
+impl<L: Language> fmt::Debug for SyntaxToken<L> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.cursor(), f)
+    }
+}
+
+impl<L: Language> fmt::Display for SyntaxToken<L> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self.text(), f)
+    }
+}
+
+impl<L: Language> From<SyntaxNode<L>> for SyntaxElement<L> {
+    fn from(node: SyntaxNode<L>) -> SyntaxElement<L> {
+        NodeOrToken::Node(node)
+    }
+}
+
+impl<L: Language> From<SyntaxToken<L>> for SyntaxElement<L> {
+    fn from(token: SyntaxToken<L>) -> SyntaxElement<L> {
+        NodeOrToken::Token(token)
+    }
+}
+
+impl<L: Language> From<api::SyntaxNode<L>> for SyntaxNode<L> {
+    fn from(node: api::SyntaxNode<L>) -> SyntaxNode<L> {
+        SyntaxNode::from_pos(Position::of(red::SyntaxNode::from(node).into()))
+    }
+}
+
+impl<L: Language> From<api::SyntaxToken<L>> for SyntaxToken<L> {
+    fn from(token: api::SyntaxToken<L>) -> SyntaxToken<L> {
+        SyntaxToken::from_pos(Position::of(red::SyntaxToken::from(token).into()))
+    }
+}
+
+impl<L: Language> From<api::SyntaxElement<L>> for SyntaxElement<L> {
+    fn from(element: api::SyntaxElement<L>) -> SyntaxElement<L> {
+        match element {
+            NodeOrToken::Node(it) => NodeOrToken::Node(it.into()),
+            NodeOrToken::Token(it) => NodeOrToken::Token(it.into()),
+        }
+    }
+}
+
+// endregion
+
+// region: iterators
+
+#[derive(Debug, Clone)]
+pub struct SyntaxNodeChildren<L: Language> {
+    raw: Children,
+    _p: PhantomData<fn() -> L>,
+}
+
+impl<L: Language> Iterator for SyntaxNodeChildren<L> {
+    type Item = SyntaxNode<L>;
+    fn next(&mut self) -> Option<SyntaxNode<L>> {
+        self.raw.find(Position::is_node).map(SyntaxNode::from_pos)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SyntaxElementChildren<L: Language> {
+    raw: Children,
+    _p: PhantomData<fn() -> L>,
+}
+
+impl<L: Language> Iterator for SyntaxElementChildren<L> {
+    type Item = SyntaxElement<L>;
+    fn next(&mut self) -> Option<SyntaxElement<L>> {
+        self.raw.next().map(SyntaxElement::from_pos)
+    }
+}
+
+pub struct Preorder<L: Language> {
+    raw: Walk,
+    _p: PhantomData<fn() -> L>,
+}
+
+impl<L: Language> Preorder<L> {
+    pub fn skip_subtree(&mut self) {
+        self.raw.skip_subtree = true;
+    }
+}
+
+impl<L: Language> Iterator for Preorder<L> {
+    type Item = WalkEvent<SyntaxNode<L>>;
+    fn next(&mut self) -> Option<WalkEvent<SyntaxNode<L>>> {
+        self.raw.next().map(|it| it.map(SyntaxNode::from_pos))
+    }
+}
+
+pub struct PreorderWithTokens<L: Language> {
+    raw: Walk,
+    _p: PhantomData<fn() -> L>,
+}
+
+impl<L: Language> PreorderWithTokens<L> {
+    pub fn skip_subtree(&mut self) {
+        self.raw.skip_subtree = true;
+    }
+}
+
+impl<L: Language> Iterator for PreorderWithTokens<L> {
+    type Item = WalkEvent<SyntaxElement<L>>;
+    fn next(&mut self) -> Option<WalkEvent<SyntaxElement<L>>> {
+        self.raw.next().map(|it| it.map(SyntaxElement::from_pos))
+    }
+}
+
+// endregion
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use crate::{test_utils::TestLang, GreenNodeBuilder, SyntaxKind};
+
+    use super::*;
+
+    const ROOT: SyntaxKind = SyntaxKind(0);
+    const WORD: SyntaxKind = SyntaxKind(1);
+    const LIST: SyntaxKind = SyntaxKind(2);
+
+    // ROOT("rule" LIST("a" "b"))
+    fn tree() -> api::SyntaxNode<TestLang> {
+        let mut builder = GreenNodeBuilder::new();
+        builder.start_node(ROOT);
+        builder.token(WORD, "rule");
+        builder.start_node(LIST);
+        builder.token(WORD, "a");
+        builder.token(WORD, "b");
+        builder.finish_node();
+        builder.finish_node();
+        api::SyntaxNode::new_root(builder.finish())
+    }
+
+    #[test]
+    fn snapshot_is_send_and_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<SyntaxNode<TestLang>>();
+        assert_send_sync::<SyntaxToken<TestLang>>();
+    }
+
+    #[test]
+    fn snapshot_roundtrip_across_threads() {
+        let root = tree();
+        let token = root.last_token().unwrap();
+        let snapshot = SyntaxToken::from(token.clone());
+        let prev = thread::spawn(move || {
+            let prev = snapshot.prev_token().unwrap();
+            assert_eq!(prev.text(), "a");
+            assert_eq!(prev.text_range(), TextRange::new(4.into(), 5.into()));
+            prev
+        })
+        .join()
+        .unwrap();
+
+        // The cursor found in the original tree is the original cursor.
+        assert_eq!(prev.cursor_in(&root), token.prev_token());
+        assert_eq!(prev.cursor().text_range(), prev.text_range());
+    }
+
+    #[test]
+    fn snapshot_navigation_matches_cursors() {
+        let root = tree();
+        let snapshot = SyntaxNode::from(root.clone());
+        assert_eq!(snapshot.text(), "ruleab");
+        assert_eq!(format!("{snapshot:?}"), format!("{root:?}"));
+        assert_eq!(format!("{snapshot:#?}"), format!("{root:#?}"));
+
+        assert!(snapshot
+            .preorder_with_tokens()
+            .map(|it| format!("{it:?}"))
+            .eq(root.preorder_with_tokens().map(|it| format!("{it:?}"))));
+
+        let list = snapshot.children().next().unwrap();
+        assert_eq!(list.kind(), LIST);
+        assert_eq!(list.index(), 1);
+        assert_eq!(list.parent().as_ref(), Some(&snapshot));
+        assert_eq!(list.prev_sibling_or_token().unwrap().kind(), WORD);
+        assert_eq!(list.green(), &*root.last_child().unwrap().green());
+        assert_eq!(list.to_string(), "ab");
+
+        let b = snapshot.token_at_offset(6.into()).right_biased().unwrap();
+        assert_eq!(b.text(), "b");
+        assert!(b.ancestors().map(|it| it.kind()).eq([LIST, ROOT]));
+        assert_eq!(
+            snapshot.token_at_offset(5.into()).left_biased().unwrap(),
+            b.prev_token().unwrap()
+        );
+        let covering = snapshot.covering_element(TextRange::new(4.into(), 6.into()));
+        assert_eq!(covering, list.clone().into());
+        assert_eq!(
+            list.clone_subtree().text_range(),
+            TextRange::up_to(2.into())
+        );
+
+        // Other trees, or edited ones, are rejected.
+        assert_eq!(list.cursor_in(&tree()), None);
+        let mutable = root.clone_for_update();
+        let list_cursor = list.cursor_in(&mutable).unwrap();
+        assert!(list_cursor.is_mutable());
+        list_cursor.detach();
+        assert_eq!(list.cursor_in(&mutable), None);
+    }
+
+    #[test]
+    fn snapshot_steps_match_cursors() {
+        // ROOT("rule" LIST("a" LIST() "b") LIST())
+        let mut builder = GreenNodeBuilder::new();
+        builder.start_node(ROOT);
+        builder.token(WORD, "rule");
+        builder.start_node(LIST);
+        builder.token(WORD, "a");
+        builder.start_node(LIST);
+        builder.finish_node();
+        builder.token(WORD, "b");
+        builder.finish_node();
+        builder.start_node(LIST);
+        builder.finish_node();
+        builder.finish_node();
+        let root = api::SyntaxNode::<TestLang>::new_root(builder.finish());
+
+        let snap = SyntaxElement::from;
+        for element in root.descendants_with_tokens() {
+            let snapshot = snap(element.clone());
+            assert_eq!(snapshot.text_range(), element.text_range());
+            assert_eq!(snapshot.parent(), element.parent().map(SyntaxNode::from));
+            assert_eq!(
+                snapshot.next_sibling_or_token(),
+                element.next_sibling_or_token().map(snap)
+            );
+            assert_eq!(
+                snapshot.prev_sibling_or_token(),
+                element.prev_sibling_or_token().map(snap)
+            );
+            match (element, snapshot) {
+                (NodeOrToken::Node(node), NodeOrToken::Node(snapshot)) => {
+                    assert!(snapshot
+                        .children_with_tokens()
+                        .eq(node.children_with_tokens().map(snap)));
+                    assert!(snapshot
+                        .children()
+                        .eq(node.children().map(SyntaxNode::from)));
+                    assert_eq!(
+                        snapshot.first_child(),
+                        node.first_child().map(SyntaxNode::from)
+                    );
+                    assert_eq!(
+                        snapshot.last_child(),
+                        node.last_child().map(SyntaxNode::from)
+                    );
+                    assert_eq!(
+                        snapshot.last_child_or_token(),
+                        node.last_child_or_token().map(snap)
+                    );
+                    assert_eq!(
+                        snapshot.next_sibling(),
+                        node.next_sibling().map(SyntaxNode::from)
+                    );
+                    assert_eq!(
+                        snapshot.prev_sibling(),
+                        node.prev_sibling().map(SyntaxNode::from)
+                    );
+                    assert_eq!(
+                        snapshot.first_token(),
+                        node.first_token().map(SyntaxToken::from)
+                    );
+                    assert_eq!(
+                        snapshot.last_token(),
+                        node.last_token().map(SyntaxToken::from)
+                    );
+                    assert!(snapshot
+                        .siblings(Direction::Prev)
+                        .eq(node.siblings(Direction::Prev).map(SyntaxNode::from)));
+                    assert!(snapshot
+                        .preorder()
+                        .map(|it| format!("{it:?}"))
+                        .eq(node.preorder().map(|it| format!("{it:?}"))));
+                    assert!(snapshot
+                        .descendants_with_tokens()
+                        .eq(node.descendants_with_tokens().map(snap)));
+                }
+                (NodeOrToken::Token(token), NodeOrToken::Token(snapshot)) => {
+                    assert_eq!(
+                        snapshot.next_token(),
+                        token.next_token().map(SyntaxToken::from)
+                    );
+                    assert_eq!(
+                        snapshot.prev_token(),
+                        token.prev_token().map(SyntaxToken::from)
+                    );
+                    assert!(snapshot
+                        .siblings_with_tokens(Direction::Next)
+                        .eq(token.siblings_with_tokens(Direction::Next).map(snap)));
+                }
+                _ => unreachable!(),
+            }
+        }
+
+        let snapshot = SyntaxNode::from(root.clone());
+        for offset in 0..=6 {
+            let offset = offset.into();
+            assert!(snapshot
+                .token_at_offset(offset)
+                .eq(root.token_at_offset(offset).map(SyntaxToken::from)));
+        }
+        let list = snapshot.children().next().unwrap();
+        let mut preorder = snapshot.preorder_with_tokens();
+        preorder.find(|it| matches!(it, WalkEvent::Enter(it) if *it == list.clone().into()));
+        preorder.skip_subtree();
+        assert!(matches!(preorder.next(), Some(WalkEvent::Leave(it)) if it == list.clone().into()));
+
+        let b = list.last_token().unwrap();
+        let replaced = b.replace_with(GreenToken::new(WORD, "c"));
+        assert_eq!(replaced.to_string(), "ruleac");
+        assert_eq!(
+            replaced,
+            b.cursor().replace_with(GreenToken::new(WORD, "c"))
+        );
+    }
+
+    #[test]
+    fn snapshot_of_parentless_token() {
+        let token = tree().clone_for_update().first_token().unwrap();
+        token.detach();
+        let snapshot = SyntaxToken::from(token.clone());
+        assert_eq!(snapshot.parent(), None);
+        assert_eq!(snapshot.text(), "rule");
+        assert_eq!(snapshot.text_range(), token.text_range());
+        assert_eq!(snapshot.next_token(), None);
+        assert_eq!(snapshot.cursor().text(), "rule");
+        assert_eq!(snapshot.cursor_in(&tree()), None);
+    }
+}
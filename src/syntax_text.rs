@@ -17,6 +17,14 @@ impl SyntaxText {
         SyntaxText { node, range }
     }
 
+    pub(crate) fn node(&self) -> &SyntaxNode {
+        &self.node
+    }
+
+    pub(crate) fn range(&self) -> TextRange {
+        self.range
+    }
+
     pub fn len(&self) -> TextSize {
         self.range.len()
     }
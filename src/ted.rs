@@ -0,0 +1,296 @@
+//! Primitive tree editor, ed for trees.
+//!
+//! The `splice_children` and `detach` primitives force callers to compute
+//! child indices by hand. The functions here instead work with positions
+//! relative to existing elements. All of them operate on mutable trees (see
+//! `SyntaxNode::clone_for_update`) and panic if given an immutable element.
+
+use std::ops::RangeInclusive;
+
+use crate::{Language, NodeOrToken, SyntaxElement, SyntaxNode};
+
+/// A place in a mutable tree, where new elements can be inserted.
+#[derive(Debug, Clone)]
+pub struct Position<L: Language> {
+    repr: PositionRepr<L>,
+}
+
+#[derive(Debug, Clone)]
+enum PositionRepr<L: Language> {
+    FirstChild(SyntaxNode<L>),
+    After(SyntaxElement<L>),
+}
+
+impl<L: Language> Position<L> {
+    /// The position right after `elem`, which must not be a root.
+    pub fn after(elem: impl Into<SyntaxElement<L>>) -> Position<L> {
+        let repr = PositionRepr::After(elem.into());
+        Position { repr }
+    }
+
+    /// The position right before `elem`, which must not be a root.
+    pub fn before(elem: impl Into<SyntaxElement<L>>) -> Position<L> {
+        let elem = elem.into();
+        let repr = match elem.prev_sibling_or_token() {
+            Some(it) => PositionRepr::After(it),
+            None => PositionRepr::FirstChild(
+                elem.parent()
+                    .unwrap_or_else(|| panic!("no position before a root: {:?}", elem)),
+            ),
+        };
+        Position { repr }
+    }
+
+    /// The position before the first child of `node`.
+    pub fn first_child_of(node: &SyntaxNode<L>) -> Position<L> {
+        let repr = PositionRepr::FirstChild(node.clone());
+        Position { repr }
+    }
+
+    /// The position after the last child of `node`.
+    pub fn last_child_of(node: &SyntaxNode<L>) -> Position<L> {
+        let repr = match node.last_child_or_token() {
+            Some(it) => PositionRepr::After(it),
+            None => PositionRepr::FirstChild(node.clone()),
+        };
+        Position { repr }
+    }
+
+    /// The node the position is in, and the index of the position among its
+    /// children.
+    fn place(&self) -> (SyntaxNode<L>, usize) {
+        match &self.repr {
+            PositionRepr::FirstChild(parent) => (parent.clone(), 0),
+            PositionRepr::After(child) => {
+                let parent = child
+                    .parent()
+                    .unwrap_or_else(|| panic!("no position after a root: {:?}", child));
+                (parent, child.index() + 1)
+            }
+        }
+    }
+}
+
+/// Inserts `elem` at `position`, see [`insert_all`].
+pub fn insert<L: Language>(position: Position<L>, elem: impl Into<SyntaxElement<L>>) {
+    insert_all(position, vec![elem.into()])
+}
+
+/// Inserts `elements` at `position`. Elements which are still attached to
+/// the tree of `position` are moved, elements of other trees must be detached
+/// first.
+pub fn insert_all<L: Language>(position: Position<L>, elements: Vec<SyntaxElement<L>>) {
+    let parent = position.place().0;
+    assert_mutable(&parent.clone().into());
+    let tree = root(&parent.clone().into());
+    for elem in &elements {
+        assert_mutable(elem);
+        if elem.parent().is_some() {
+            assert!(
+                root(elem) == tree,
+                "can't move {:?} between trees, detach it first",
+                elem,
+            );
+        }
+        if let NodeOrToken::Node(node) = elem {
+            assert!(
+                !parent.ancestors().any(|it| &it == node),
+                "can't insert {:?} into its own subtree",
+                node,
+            );
+        }
+        if let PositionRepr::After(anchor) = &position.repr {
+            assert!(elem != anchor, "can't insert {:?} after itself", elem);
+        }
+    }
+    // Detaching may shift siblings of the position, so the index is computed
+    // only afterwards.
+    elements.iter().for_each(|it| it.detach());
+    let (parent, index) = position.place();
+    parent.splice_children(index..index, elements);
+}
+
+/// Detaches `elem` from its parent.
+pub fn remove<L: Language>(elem: impl Into<SyntaxElement<L>>) {
+    let elem = elem.into();
+    assert_mutable(&elem);
+    elem.detach()
+}
+
+/// Removes the siblings from `range.start()` to `range.end()`, inclusive.
+pub fn remove_all<L: Language>(range: RangeInclusive<SyntaxElement<L>>) {
+    replace_all(range, Vec::new())
+}
+
+/// Removes the siblings yielded by `elements`, in any order. Panics unless
+/// they form a contiguous run of children of one parent.
+pub fn remove_all_iter<L: Language>(elements: impl IntoIterator<Item = SyntaxElement<L>>) {
+    let mut elements = elements.into_iter().collect::<Vec<_>>();
+    let (first, rest) = match elements.split_first() {
+        Some(it) => it,
+        None => return,
+    };
+    let parent = first.parent();
+    for elem in rest {
+        assert!(
+            elem.parent() == parent,
+            "not siblings: {:?} and {:?}",
+            first,
+            elem,
+        );
+    }
+    elements.sort_by_key(|it| it.index());
+    for pair in elements.windows(2) {
+        assert!(
+            pair[0].index() + 1 == pair[1].index(),
+            "not a contiguous run of siblings: {:?} and {:?}",
+            pair[0],
+            pair[1],
+        );
+    }
+    let first = elements.first().unwrap().clone();
+    let last = elements.pop().unwrap();
+    remove_all(first..=last)
+}
+
+/// Replaces `old` with `new`.
+pub fn replace<L: Language>(old: impl Into<SyntaxElement<L>>, new: impl Into<SyntaxElement<L>>) {
+    replace_with_many(old, vec![new.into()])
+}
+
+/// Replaces `old` with the elements of `new`.
+pub fn replace_with_many<L: Language>(
+    old: impl Into<SyntaxElement<L>>,
+    new: Vec<SyntaxElement<L>>,
+) {
+    let old = old.into();
+    replace_all(old.clone()..=old, new)
+}
+
+/// Replaces the siblings from `range.start()` to `range.end()`, inclusive,
+/// with `new`, see [`insert_all`] for which elements can be inserted.
+pub fn replace_all<L: Language>(
+    range: RangeInclusive<SyntaxElement<L>>,
+    new: Vec<SyntaxElement<L>>,
+) {
+    let (first, last) = range.into_inner();
+    assert_mutable(&first);
+    assert_mutable(&last);
+    let parent = first
+        .parent()
+        .unwrap_or_else(|| panic!("can't replace a root: {:?}", first));
+    assert_eq!(
+        Some(&parent),
+        last.parent().as_ref(),
+        "range ends are not siblings: {:?} and {:?}",
+        first,
+        last,
+    );
+    assert!(
+        first.index() <= last.index(),
+        "invalid range: {:?}..={:?}",
+        first,
+        last
+    );
+
+    insert_all(Position::after(last.clone()), new);
+    parent.splice_children(first.index()..last.index() + 1, Vec::new());
+}
+
+/// Inserts `child` after the last child of `node`.
+pub fn append_child<L: Language>(node: &SyntaxNode<L>, child: impl Into<SyntaxElement<L>>) {
+    insert(Position::last_child_of(node), child)
+}
+
+/// Inserts `child` before the first child of `node`.
+pub fn prepend_child<L: Language>(node: &SyntaxNode<L>, child: impl Into<SyntaxElement<L>>) {
+    insert(Position::first_child_of(node), child)
+}
+
+fn assert_mutable<L: Language>(elem: &SyntaxElement<L>) {
+    assert!(elem.is_mutable(), "immutable tree: {:?}", elem);
+}
+
+fn root<L: Language>(elem: &SyntaxElement<L>) -> SyntaxNode<L> {
+    elem.ancestors().last().unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{test_utils::TestLang, GreenNodeBuilder, SyntaxKind};
+
+    use super::*;
+
+    fn parse(text: &str) -> SyntaxNode<TestLang> {
+        let mut builder = GreenNodeBuilder::new();
+        builder.start_node(SyntaxKind(0));
+        for c in text.chars() {
+            builder.token(SyntaxKind(1), &c.to_string());
+        }
+        builder.finish_node();
+        SyntaxNode::<TestLang>::new_root(builder.finish()).clone_for_update()
+    }
+
+    fn nth(root: &SyntaxNode<TestLang>, n: usize) -> SyntaxElement<TestLang> {
+        root.children_with_tokens().nth(n).unwrap()
+    }
+
+    #[test]
+    fn edits_by_position() {
+        let root = parse("abcd");
+        let other = parse("xy");
+
+        let x = nth(&other, 0);
+        remove(x.clone());
+        insert(Position::before(nth(&root, 0)), x);
+        assert_eq!(root.to_string(), "xabcd");
+        assert_eq!(other.to_string(), "y");
+
+        // Moving an element within its own parent.
+        insert(Position::after(nth(&root, 3)), nth(&root, 1));
+        assert_eq!(root.to_string(), "xbcad");
+
+        let y = nth(&other, 0);
+        remove(y.clone());
+        replace_all(nth(&root, 1)..=nth(&root, 2), vec![y]);
+        assert_eq!(root.to_string(), "xyad");
+
+        remove_all_iter(vec![nth(&root, 2), nth(&root, 1)]);
+        assert_eq!(root.to_string(), "xd");
+
+        append_child(&root, parse("e"));
+        assert_eq!(root.to_string(), "xde");
+    }
+
+    #[test]
+    fn remove_all_iter_sorts_siblings() {
+        let root = parse("abcde");
+        remove_all_iter(vec![nth(&root, 2), nth(&root, 1), nth(&root, 3)]);
+        assert_eq!(root.to_string(), "ae");
+        remove_all_iter(Vec::<SyntaxElement<TestLang>>::new());
+        assert_eq!(root.to_string(), "ae");
+    }
+
+    #[test]
+    #[should_panic(expected = "not a contiguous run of siblings")]
+    fn remove_all_iter_rejects_gaps() {
+        let root = parse("abcd");
+        remove_all_iter(vec![nth(&root, 0), nth(&root, 2)]);
+    }
+
+    #[test]
+    #[should_panic(expected = "can't move")]
+    fn insert_rejects_elements_of_other_trees() {
+        let root = parse("ab");
+        let other = parse("x");
+        insert(Position::after(nth(&root, 0)), nth(&other, 0));
+    }
+
+    #[test]
+    #[should_panic(expected = "immutable tree")]
+    fn rejects_immutable_trees() {
+        let root = parse("ab");
+        let immutable = SyntaxNode::<TestLang>::new_root(root.green().into_owned());
+        remove(nth(&immutable, 0));
+    }
+}
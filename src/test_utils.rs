@@ -0,0 +1,44 @@
+//! The language the unit tests build their trees in.
+
+use crate::{GreenNode, Language, SyntaxKind};
+
+/// Whitespace, the only trivia of [`TestLang`]. Tests which don't deal with
+/// trivia use kind 0 for whatever they like.
+pub(crate) const WS: SyntaxKind = SyntaxKind(0);
+
+/// A language whose kinds are plain [`SyntaxKind`]s.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub(crate) enum TestLang {}
+
+impl Language for TestLang {
+    type Kind = SyntaxKind;
+
+    fn kind_from_raw(raw: SyntaxKind) -> SyntaxKind {
+        raw
+    }
+    fn kind_to_raw(kind: SyntaxKind) -> SyntaxKind {
+        kind
+    }
+    fn is_trivia(kind: SyntaxKind) -> bool {
+        kind == WS
+    }
+}
+
+/// Drops a tree without recursing once per level, which overflows the stack
+/// for deep trees. Subtrees are visited once per parent, so this is meant for
+/// trees without much sharing.
+pub(crate) fn drop_deep(tree: GreenNode) {
+    let mut nodes = vec![tree];
+    let mut i = 0;
+    while i < nodes.len() {
+        let children = nodes[i].children().filter_map(|it| it.into_node());
+        let children: Vec<GreenNode> = children.map(|it| it.to_owned()).collect();
+        nodes.extend(children);
+        i += 1;
+    }
+    // Parents come first, so each node is freed while its children are still
+    // held by `nodes`.
+    for node in nodes {
+        drop(node);
+    }
+}
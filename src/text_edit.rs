@@ -0,0 +1,133 @@
+//! Text edits, the input of incremental reparsing.
+
+use crate::{TextRange, TextSize};
+
+/// A single atomic change: `delete` is replaced with `insert`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Indel {
+    /// The text replacing `delete`.
+    pub insert: String,
+    /// Refers to offsets in the original text.
+    pub delete: TextRange,
+}
+
+impl Indel {
+    /// Inserts `text` at `offset`.
+    pub fn insert(offset: TextSize, text: String) -> Indel {
+        Indel::replace(TextRange::empty(offset), text)
+    }
+
+    /// Deletes the text in `range`.
+    pub fn delete(range: TextRange) -> Indel {
+        Indel::replace(range, String::new())
+    }
+
+    /// Replaces the text in `range` with `replace_with`.
+    pub fn replace(range: TextRange, replace_with: String) -> Indel {
+        Indel {
+            delete: range,
+            insert: replace_with,
+        }
+    }
+
+    /// Length of the text after the edit, given the length before it.
+    pub fn apply_to_len(&self, len: TextSize) -> TextSize {
+        len - self.delete.len() + TextSize::of(&self.insert)
+    }
+
+    /// Applies the edit to `text`, which must be the original text.
+    pub fn apply(&self, text: &mut String) {
+        let start: usize = self.delete.start().into();
+        let end: usize = self.delete.end().into();
+        text.replace_range(start..end, &self.insert);
+    }
+}
+
+/// A set of disjoint [`Indel`]s, sorted by offset.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
+pub struct TextEdit {
+    indels: Vec<Indel>,
+}
+
+impl TextEdit {
+    /// Creates an edit from indels referring to the same original text.
+    /// Panics if the indels overlap.
+    pub fn new(mut indels: Vec<Indel>) -> TextEdit {
+        indels.sort_by_key(|it| (it.delete.start(), it.delete.end()));
+        for pair in indels.windows(2) {
+            assert!(
+                pair[0].delete.end() <= pair[1].delete.start(),
+                "overlapping indels: {:?} and {:?}",
+                pair[0],
+                pair[1],
+            );
+        }
+        TextEdit { indels }
+    }
+
+    /// Number of indels in the edit.
+    pub fn len(&self) -> usize {
+        self.indels.len()
+    }
+
+    /// Whether the edit changes nothing at all.
+    pub fn is_empty(&self) -> bool {
+        self.indels.is_empty()
+    }
+
+    /// The indels, sorted by offset.
+    pub fn iter(&self) -> std::slice::Iter<'_, Indel> {
+        self.indels.iter()
+    }
+
+    /// Applies all indels to `text`, which must be the original text.
+    pub fn apply(&self, text: &mut String) {
+        // Going backwards keeps the offsets of the remaining indels valid.
+        for indel in self.indels.iter().rev() {
+            indel.apply(text);
+        }
+    }
+
+    /// Maps an offset in the original text to the edited one. Returns `None`
+    /// if the offset is inside a deleted range.
+    pub fn apply_to_offset(&self, offset: TextSize) -> Option<TextSize> {
+        let mut res = offset;
+        for indel in &self.indels {
+            if indel.delete.start() >= offset {
+                break;
+            }
+            if offset < indel.delete.end() {
+                return None;
+            }
+            res += TextSize::of(&indel.insert);
+            res -= indel.delete.len();
+        }
+        Some(res)
+    }
+}
+
+impl From<Indel> for TextEdit {
+    fn from(indel: Indel) -> TextEdit {
+        TextEdit {
+            indels: vec![indel],
+        }
+    }
+}
+
+impl IntoIterator for TextEdit {
+    type Item = Indel;
+    type IntoIter = std::vec::IntoIter<Indel>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.indels.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a TextEdit {
+    type Item = &'a Indel;
+    type IntoIter = std::slice::Iter<'a, Indel>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
@@ -0,0 +1,154 @@
+//! Attaching trivia, as classified by [`Language::is_trivia`], to the
+//! significant tokens around it.
+
+use crate::{Language, SyntaxNode, SyntaxToken};
+
+/// How the trivia between two significant tokens is split into the trailing
+/// trivia of the first and the leading trivia of the second.
+///
+/// Trivia before the first significant token of a tree is always leading, and
+/// after the last one always trailing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum TriviaAttachment {
+    /// Everything is leading trivia of the next token.
+    Leading,
+    /// Everything is trailing trivia of the previous token.
+    Trailing,
+    /// Trivia on the same line as the previous token is trailing, starting
+    /// with the first trivia token containing a newline it is leading.
+    #[default]
+    TrailingUntilNewline,
+}
+
+impl TriviaAttachment {
+    /// Index in `trivia` where the leading part starts.
+    fn split<L: Language>(self, trivia: &[SyntaxToken<L>]) -> usize {
+        match self {
+            TriviaAttachment::Leading => 0,
+            TriviaAttachment::Trailing => trivia.len(),
+            TriviaAttachment::TrailingUntilNewline => trivia
+                .iter()
+                .position(|it| it.text().contains('\n'))
+                .unwrap_or(trivia.len()),
+        }
+    }
+}
+
+impl<L: Language> SyntaxToken<L> {
+    pub fn is_trivia(&self) -> bool {
+        L::is_trivia(self.kind())
+    }
+
+    /// The trivia attached in front of this token according to
+    /// [`Language::trivia_attachment`], in text order. Empty for trivia.
+    pub fn leading_trivia(&self) -> impl Iterator<Item = SyntaxToken<L>> {
+        let mut trivia = Vec::new();
+        let mut prev_significant = false;
+        if !self.is_trivia() {
+            let mut token = self.prev_token();
+            while let Some(it) = token {
+                if !it.is_trivia() {
+                    prev_significant = true;
+                    break;
+                }
+                token = it.prev_token();
+                trivia.push(it);
+            }
+            trivia.reverse();
+        }
+        if prev_significant {
+            let split = L::trivia_attachment().split(&trivia);
+            trivia.drain(..split);
+        }
+        trivia.into_iter()
+    }
+
+    /// The trivia attached after this token according to
+    /// [`Language::trivia_attachment`], in text order. Empty for trivia.
+    pub fn trailing_trivia(&self) -> impl Iterator<Item = SyntaxToken<L>> {
+        let mut trivia = Vec::new();
+        let mut next_significant = false;
+        if !self.is_trivia() {
+            let mut token = self.next_token();
+            while let Some(it) = token {
+                if !it.is_trivia() {
+                    next_significant = true;
+                    break;
+                }
+                token = it.next_token();
+                trivia.push(it);
+            }
+        }
+        if next_significant {
+            let split = L::trivia_attachment().split(&trivia);
+            trivia.truncate(split);
+        }
+        trivia.into_iter()
+    }
+}
+
+impl<L: Language> SyntaxNode<L> {
+    /// All tokens of this node which are not trivia.
+    pub fn non_trivia_tokens(&self) -> impl Iterator<Item = SyntaxToken<L>> {
+        self.descendants_with_tokens()
+            .filter_map(|it| it.into_token())
+            .filter(|it| !it.is_trivia())
+    }
+
+    pub fn first_non_trivia_token(&self) -> Option<SyntaxToken<L>> {
+        self.non_trivia_tokens().next()
+    }
+
+    pub fn last_non_trivia_token(&self) -> Option<SyntaxToken<L>> {
+        let start = self.text_range().start();
+        let mut token = self.last_token();
+        while let Some(it) = token {
+            if !it.is_trivia() {
+                return Some(it);
+            }
+            token = it
+                .prev_token()
+                .filter(|it| it.text_range().start() >= start);
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        test_utils::{TestLang, WS},
+        GreenNodeBuilder, SyntaxKind,
+    };
+
+    use super::*;
+
+    #[test]
+    fn attaches_trivia() {
+        // ROOT(" " ITEM("a" " " "//c" "\n" "b") " ")
+        let mut builder = GreenNodeBuilder::new();
+        builder.start_node(SyntaxKind(2));
+        builder.token(WS, " ");
+        builder.start_node(SyntaxKind(3));
+        for (kind, text) in [(1, "a"), (0, " "), (0, "//c"), (0, "\n"), (1, "b")] {
+            builder.token(SyntaxKind(kind), text);
+        }
+        builder.finish_node();
+        builder.token(WS, " ");
+        builder.finish_node();
+        let root = SyntaxNode::<TestLang>::new_root(builder.finish());
+
+        let texts = |tokens: &mut dyn Iterator<Item = SyntaxToken<TestLang>>| {
+            tokens.map(|it| it.text().to_string()).collect::<Vec<_>>()
+        };
+        let item = root.first_child().unwrap();
+        let a = item.first_non_trivia_token().unwrap();
+        let b = item.last_non_trivia_token().unwrap();
+        assert_eq!(texts(&mut item.non_trivia_tokens()), ["a", "b"]);
+        assert_eq!(texts(&mut a.leading_trivia()), [" "]);
+        assert_eq!(texts(&mut a.trailing_trivia()), [" ", "//c"]);
+        assert_eq!(texts(&mut b.leading_trivia()), ["\n"]);
+        assert_eq!(texts(&mut b.trailing_trivia()), [" "]);
+        assert_eq!(a.next_token().unwrap().leading_trivia().count(), 0);
+    }
+}